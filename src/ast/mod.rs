@@ -0,0 +1,270 @@
+// Abstract Syntax Tree (AST) definitions for SysY language
+
+mod pretty;
+
+pub use pretty::pretty_print;
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum DataType {
+    Int,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum FuncType {
+    Void,
+    Int,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind", content = "value"))]
+pub enum GlobalItem {
+    Decl(Decl),
+    FuncDef(FuncDef),
+    FuncDecl(FuncDecl),
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CompUnit {
+    pub items: Vec<GlobalItem>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FuncDef {
+    pub func_type: FuncType,
+    pub func_name: String,
+    pub params: Vec<FuncFParam>,
+    pub block: Block,
+}
+
+/// A function declaration without a body, e.g. `extern int getint();`
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FuncDecl {
+    pub func_type: FuncType,
+    pub func_name: String,
+    pub params: Vec<FuncFParam>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FuncFParam {
+    pub base_type: DataType,
+    pub name: String,
+    pub dims: Option<Vec<Expr>>, // None if not an array
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Block {
+    pub items: Vec<BlockItem>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind", content = "value"))]
+pub enum BlockItem {
+    Decl(Decl),
+    Stmt(Stmt),
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind", content = "value"))]
+pub enum Decl {
+    Const {
+        var_type: DataType,
+        var_name: String,
+        init_list: InitList,
+    },
+    Var {
+        var_type: DataType,
+        var_name: String,
+        init_list: Option<InitList>,
+    },
+    Array {
+        var_type: DataType,
+        var_name: String,
+        dims: Vec<Expr>, // Array dimensions
+        init_list: Option<InitList>,
+    },
+    ConstArray {
+        var_type: DataType,
+        var_name: String,
+        dims: Vec<Expr>, // Array dimensions
+        init_list: Option<InitList>,
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind", content = "value"))]
+pub enum InitList {
+    Expr(Expr),
+    List(Vec<InitList>),
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind", content = "value"))]
+pub enum Stmt {
+    Return {
+        expr: Option<Expr>,
+    },
+    Assign {
+        lval: Expr,
+        op: Option<BinaryOp>, // Some for compound assignments, e.g. `+=`
+        expr: Expr,
+    },
+    Expression {
+        expr: Option<Expr>,
+    },
+    Block {
+        block: Block,
+    },
+    If {
+        cond: Expr,
+        then_body: Box<Stmt>, // Boxed to avoid recursive size issues
+        else_body: Option<Box<Stmt>>,
+    },
+    While {
+        cond: Expr,
+        body: Box<Stmt>,
+    },
+    // `do body while (cond);`: the body runs at least once
+    DoWhile {
+        body: Box<Stmt>,
+        cond: Expr,
+    },
+    // `for (init; cond; step) body`. The variables declared by `init` are
+    // scoped to the loop
+    For {
+        init: Vec<BlockItem>, // Declarations, or at most one statement
+        cond: Option<Expr>,   // None: loop until `break`
+        step: Option<Box<Stmt>>,
+        body: Box<Stmt>,
+    },
+    Break,
+    Continue,
+    Label(String), // `label:`, the target of a `goto`
+    Goto(String),
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind", content = "value"))]
+pub enum Expr {
+    Binary {
+        op: BinaryOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    Unary {
+        op: UnaryOp,
+        expr: Box<Expr>,
+    },
+    // Note that constant variable references are also treated as LVal here
+    // Their values will be resolved during constant expression evaluation
+    LVal {
+        name: String,
+        indices: Option<Vec<Expr>>,    // Empty if not an array
+    },
+    Number(i32),
+    Call {
+        func_name: String,
+        args: Vec<Expr>,
+    },
+    // `lhs, rhs`: evaluates lhs for its side effects, then yields rhs
+    Comma {
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    // `cond ? then : else_`: evaluates only the operand that is selected
+    Ternary {
+        cond: Box<Expr>,
+        then: Box<Expr>,
+        else_: Box<Expr>,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Neq,
+    Lt,
+    Gt,
+    Leq,
+    Geq,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr, // Arithmetic: `int` is signed
+}
+
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum UnaryOp {
+    Pos,
+    Neg,
+    Not,    // Logical
+    BitNot, // `~`
+}
+
+impl BinaryOp {
+    /// The operator as written in the source, e.g. `<<`
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Or => "||",
+            BinaryOp::And => "&&",
+            BinaryOp::Eq => "==",
+            BinaryOp::Neq => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Gt => ">",
+            BinaryOp::Leq => "<=",
+            BinaryOp::Geq => ">=",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Mod => "%",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::BitOr => "|",
+            BinaryOp::BitXor => "^",
+            BinaryOp::Shl => "<<",
+            BinaryOp::Shr => ">>",
+        }
+    }
+}
+
+impl UnaryOp {
+    /// The operator as written in the source, e.g. `!`
+    pub fn symbol(self) -> &'static str {
+        match self {
+            UnaryOp::Pos => "+",
+            UnaryOp::Neg => "-",
+            UnaryOp::Not => "!",
+            UnaryOp::BitNot => "~",
+        }
+    }
+}
+
+impl From<DataType> for FuncType {
+    fn from(dt: DataType) -> Self {
+        match dt {
+            DataType::Int => FuncType::Int,
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,260 @@
+use super::*;
+
+/// Renders the AST as an indented tree, one node per line, with the
+/// children of a node two spaces deeper than the node itself.
+/// Operators are shown with their symbol, so that the nesting shows how the
+/// parser grouped an expression
+pub fn pretty_print(cu: &CompUnit) -> String {
+    let mut printer = Printer::default();
+    printer.line("CompUnit");
+    printer.nested(|p| {
+        for item in &cu.items {
+            p.global_item(item);
+        }
+    });
+    printer.out
+}
+
+#[derive(Default)]
+struct Printer {
+    out: String,
+    depth: usize, // Indentation level of the next line
+}
+
+impl Printer {
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.depth {
+            self.out.push_str("  ");
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    /// Prints what `f` prints one level deeper
+    fn nested(&mut self, f: impl FnOnce(&mut Self)) {
+        self.depth += 1;
+        f(self);
+        self.depth -= 1;
+    }
+
+    /// Prints `label` with what `f` prints below it
+    fn group(&mut self, label: &str, f: impl FnOnce(&mut Self)) {
+        self.line(label);
+        self.nested(f);
+    }
+
+    fn global_item(&mut self, item: &GlobalItem) {
+        match item {
+            GlobalItem::Decl(decl) => self.decl(decl),
+            GlobalItem::FuncDef(def) => {
+                let header = format!("FuncDef {} {}", func_type(def.func_type), def.func_name);
+                self.group(&header, |p| {
+                    p.params(&def.params);
+                    p.block(&def.block);
+                });
+            }
+            GlobalItem::FuncDecl(decl) => {
+                let header = format!("FuncDecl {} {}", func_type(decl.func_type), decl.func_name);
+                self.group(&header, |p| p.params(&decl.params));
+            }
+        }
+    }
+
+    fn params(&mut self, params: &[FuncFParam]) {
+        for param in params {
+            let base_type = data_type(param.base_type);
+            match &param.dims {
+                None => self.line(&format!("Param {} {}", base_type, param.name)),
+                // The first dimension of an array parameter is omitted
+                Some(dims) => {
+                    let header = format!("Param {} {}[]", base_type, param.name);
+                    self.group(&header, |p| p.exprs(dims));
+                }
+            }
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.group("Block", |p| p.block_items(&block.items));
+    }
+
+    fn block_items(&mut self, items: &[BlockItem]) {
+        for item in items {
+            match item {
+                BlockItem::Decl(decl) => self.decl(decl),
+                BlockItem::Stmt(stmt) => self.stmt(stmt),
+            }
+        }
+    }
+
+    fn decl(&mut self, decl: &Decl) {
+        match decl {
+            Decl::Const {
+                var_type,
+                var_name,
+                init_list,
+            } => {
+                let header = format!("ConstDecl {} {}", data_type(*var_type), var_name);
+                self.group(&header, |p| p.init(Some(init_list)));
+            }
+            Decl::Var {
+                var_type,
+                var_name,
+                init_list,
+            } => {
+                let header = format!("VarDecl {} {}", data_type(*var_type), var_name);
+                self.group(&header, |p| p.init(init_list.as_ref()));
+            }
+            Decl::Array {
+                var_type,
+                var_name,
+                dims,
+                init_list,
+            } => {
+                let header = format!("ArrayDecl {} {}", data_type(*var_type), var_name);
+                self.group(&header, |p| {
+                    p.group("Dims", |p| p.exprs(dims));
+                    p.init(init_list.as_ref());
+                });
+            }
+            Decl::ConstArray {
+                var_type,
+                var_name,
+                dims,
+                init_list,
+            } => {
+                let header = format!("ConstArrayDecl {} {}", data_type(*var_type), var_name);
+                self.group(&header, |p| {
+                    p.group("Dims", |p| p.exprs(dims));
+                    p.init(init_list.as_ref());
+                });
+            }
+        }
+    }
+
+    fn init(&mut self, init_list: Option<&InitList>) {
+        if let Some(init_list) = init_list {
+            self.group("Init", |p| p.init_list(init_list));
+        }
+    }
+
+    fn init_list(&mut self, init_list: &InitList) {
+        match init_list {
+            InitList::Expr(expr) => self.expr(expr),
+            InitList::List(items) => self.group("List", |p| {
+                for item in items {
+                    p.init_list(item);
+                }
+            }),
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Return { expr } => self.group("Return", |p| p.opt_expr(expr.as_ref())),
+            Stmt::Assign { lval, op, expr } => {
+                let op = op.map_or("", BinaryOp::symbol);
+                self.group(&format!("Assign {}=", op), |p| {
+                    p.expr(lval);
+                    p.expr(expr);
+                });
+            }
+            Stmt::Expression { expr } => self.group("ExprStmt", |p| p.opt_expr(expr.as_ref())),
+            Stmt::Block { block } => self.block(block),
+            Stmt::If {
+                cond,
+                then_body,
+                else_body,
+            } => self.group("If", |p| {
+                p.group("Cond", |p| p.expr(cond));
+                p.group("Then", |p| p.stmt(then_body));
+                if let Some(else_body) = else_body {
+                    p.group("Else", |p| p.stmt(else_body));
+                }
+            }),
+            Stmt::While { cond, body } => self.group("While", |p| {
+                p.group("Cond", |p| p.expr(cond));
+                p.group("Body", |p| p.stmt(body));
+            }),
+            Stmt::DoWhile { body, cond } => self.group("DoWhile", |p| {
+                p.group("Body", |p| p.stmt(body));
+                p.group("Cond", |p| p.expr(cond));
+            }),
+            Stmt::For {
+                init,
+                cond,
+                step,
+                body,
+            } => self.group("For", |p| {
+                if !init.is_empty() {
+                    p.group("Init", |p| p.block_items(init));
+                }
+                if let Some(cond) = cond {
+                    p.group("Cond", |p| p.expr(cond));
+                }
+                if let Some(step) = step {
+                    p.group("Step", |p| p.stmt(step));
+                }
+                p.group("Body", |p| p.stmt(body));
+            }),
+            Stmt::Break => self.line("Break"),
+            Stmt::Continue => self.line("Continue"),
+            Stmt::Label(name) => self.line(&format!("Label {}", name)),
+            Stmt::Goto(name) => self.line(&format!("Goto {}", name)),
+        }
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) {
+        for expr in exprs {
+            self.expr(expr);
+        }
+    }
+
+    fn opt_expr(&mut self, expr: Option<&Expr>) {
+        if let Some(expr) = expr {
+            self.expr(expr);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Binary { op, lhs, rhs } => self.group(&format!("Binary {}", op.symbol()), |p| {
+                p.expr(lhs);
+                p.expr(rhs);
+            }),
+            Expr::Unary { op, expr } => {
+                self.group(&format!("Unary {}", op.symbol()), |p| p.expr(expr));
+            }
+            Expr::LVal { name, indices } => {
+                let indices = indices.as_deref().unwrap_or_default();
+                self.group(&format!("LVal {}", name), |p| p.exprs(indices));
+            }
+            Expr::Number(value) => self.line(&format!("Number {}", value)),
+            Expr::Call { func_name, args } => {
+                self.group(&format!("Call {}", func_name), |p| p.exprs(args));
+            }
+            Expr::Comma { lhs, rhs } => self.group("Comma", |p| {
+                p.expr(lhs);
+                p.expr(rhs);
+            }),
+            Expr::Ternary { cond, then, else_ } => self.group("Ternary", |p| {
+                p.expr(cond);
+                p.expr(then);
+                p.expr(else_);
+            }),
+        }
+    }
+}
+
+fn data_type(data_type: DataType) -> &'static str {
+    match data_type {
+        DataType::Int => "int",
+    }
+}
+
+fn func_type(func_type: FuncType) -> &'static str {
+    match func_type {
+        FuncType::Void => "void",
+        FuncType::Int => "int",
+    }
+}
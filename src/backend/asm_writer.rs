@@ -1,51 +1,366 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Display;
 use std::io::{self, Write};
 
+/// Values per `.word` directive written by `write_word_array`
+const WORDS_PER_LINE: usize = 8;
+
+/// An instruction buffered by `AsmWriter`, with ABI register names
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmInst {
+    pub op: String,
+    pub args: Vec<String>,
+}
+
+/// A line of assembly buffered by `AsmWriter`
+#[derive(Debug, Clone)]
+enum AsmLine {
+    Inst(AsmInst),
+    Label(String),
+    Directive(String, Vec<String>),
+    Comment(String),
+    Blank,
+}
+
+/// Collects the assembly as structured lines, so that they can still be
+/// rewritten, and writes them out on `finish`
 pub struct AsmWriter<W: Write> {
     writer: W,
+    // Everything written since the last `finish`
+    lines: Vec<AsmLine>,
+    numeric_regs: bool, // Emit `x10` instead of `a0`, etc.
+    // Registers known to hold a constant since the last label.
+    // None unless constant reuse is enabled
+    known_constants: Option<BTreeMap<String, i32>>,
+    inst_count: usize, // Instructions written so far
+    // Registers written since the last `take_written_regs`
+    written_regs: BTreeSet<String>,
+    // Number of instructions written before each label
+    label_positions: HashMap<String, usize>,
+    peephole: bool, // See `set_peephole`
 }
 
 impl<W: Write> AsmWriter<W> {
     pub fn new(writer: W) -> Self {
-        Self { writer }
+        Self {
+            writer,
+            lines: Vec::new(),
+            numeric_regs: false,
+            known_constants: None,
+            inst_count: 0,
+            written_regs: BTreeSet::new(),
+            label_positions: HashMap::new(),
+            peephole: false,
+        }
+    }
+
+    /// Makes the output use numeric register names (`x0`-`x31`) instead of
+    /// ABI names
+    pub fn set_numeric_regs(&mut self, numeric_regs: bool) {
+        self.numeric_regs = numeric_regs;
+    }
+
+    /// Makes the writer skip loading a constant into a register that is
+    /// known to hold it already. Registers are forgotten at every label
+    /// (a block boundary), on calls and when overwritten
+    pub fn set_reuse_constants(&mut self, reuse_constants: bool) {
+        self.known_constants = reuse_constants.then(BTreeMap::new);
+    }
+
+    /// Makes the writer leave out instructions that have no effect given
+    /// the one written just before: self-moves, a move undoing the previous
+    /// one and a load of the word just stored (which becomes a move unless
+    /// it loads the register that was stored). Labels and directives end
+    /// the window, comments do not
+    pub fn set_peephole(&mut self, peephole: bool) {
+        self.peephole = peephole;
+    }
+
+    /// Number of instructions written so far
+    pub fn inst_count(&self) -> usize {
+        self.inst_count
+    }
+
+    /// Number of instructions written before `label`, if it has been
+    /// written
+    pub fn label_position(&self, label: &str) -> Option<usize> {
+        self.label_positions.get(label).copied()
+    }
+
+    /// Returns the registers written since the last call, sorted by name
+    pub fn take_written_regs(&mut self) -> BTreeSet<String> {
+        std::mem::take(&mut self.written_regs)
+    }
+
+    /// Whether `reg` is known to hold `value` (only tracked with constant reuse)
+    pub fn holds_constant(&self, reg: &str, value: i32) -> bool {
+        self.known_constants
+            .as_ref()
+            .is_some_and(|known| known.get(reg) == Some(&value))
     }
 
     pub fn write_inst(&mut self, inst: &str, args: &[&str]) -> io::Result<()> {
-        write!(self.writer, "    {}", inst)?;
-        if !args.is_empty() {
-            for (i, arg) in args.iter().enumerate() {
-                if i > 0 {
-                    write!(self.writer, ",")?;
-                }
-                write!(self.writer, " {}", arg)?;
+        if self.peephole {
+            match peephole(self.last_inst(), inst, args) {
+                Peephole::Keep => {}
+                Peephole::Drop => return Ok(()),
+                Peephole::Move(source) => return self.write_inst("mv", &[args[0], &source]),
             }
         }
-        writeln!(self.writer)
+        let Some(known) = self.known_constants.as_mut() else {
+            return self.emit_inst(inst, args);
+        };
+        let Some((reg, value)) = loaded_constant(inst, args, known) else {
+            if inst == "call" {
+                known.clear();
+            } else if writes_first_operand(inst) {
+                known.remove(args[0]);
+            }
+            return self.emit_inst(inst, args);
+        };
+        if known.get(reg) == Some(&value) {
+            return Ok(());
+        }
+        // Constants that do not fit in an immediate take two instructions
+        // to load, a copy from a register holding the value takes one
+        let source = if (-2048..=2047).contains(&value) {
+            None
+        } else {
+            known
+                .iter()
+                .find(|(_, &held)| held == value)
+                .map(|(source, _)| source.clone())
+        };
+        known.insert(reg.to_string(), value);
+        match source {
+            Some(source) => self.emit_inst("mv", &[reg, &source]),
+            None => self.emit_inst(inst, args),
+        }
+    }
+
+    fn emit_inst(&mut self, inst: &str, args: &[&str]) -> io::Result<()> {
+        self.inst_count += 1;
+        if inst == "call" {
+            self.written_regs.insert("ra".to_string());
+        } else if writes_first_operand(inst) && args.first().is_some_and(|&reg| is_register(reg)) {
+            self.written_regs.insert(args[0].to_string());
+        }
+        self.lines.push(AsmLine::Inst(AsmInst {
+            op: inst.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+        }));
+        Ok(())
+    }
+
+    /// The instruction written last, unless a label or a directive has
+    /// been written after it. Comments are skipped
+    fn last_inst(&self) -> Option<&AsmInst> {
+        let last = self
+            .lines
+            .iter()
+            .rev()
+            .find(|line| !matches!(line, AsmLine::Comment(_)))?;
+        match last {
+            AsmLine::Inst(inst) => Some(inst),
+            _ => None,
+        }
     }
 
     pub fn write_label(&mut self, label: &str) -> io::Result<()> {
-        writeln!(self.writer, "{}:", label)
+        if let Some(known) = self.known_constants.as_mut() {
+            known.clear();
+        }
+        self.label_positions
+            .insert(label.to_string(), self.inst_count);
+        self.lines.push(AsmLine::Label(label.to_string()));
+        Ok(())
     }
 
     pub fn write_directive(&mut self, directive: &str, args: &[&str]) -> io::Result<()> {
-        write!(self.writer, "    .{}", directive)?;
-        if !args.is_empty() {
-            write!(self.writer, " ")?;
-            for (i, arg) in args.iter().enumerate() {
-                if i > 0 {
-                    write!(self.writer, ", ")?;
-                }
-                write!(self.writer, "{}", arg)?;
-            }
+        self.lines.push(AsmLine::Directive(
+            directive.to_string(),
+            args.iter().map(|arg| arg.to_string()).collect(),
+        ));
+        Ok(())
+    }
+
+    /// Writes `values` as `.word` directives of up to `WORDS_PER_LINE`
+    /// comma-separated values each
+    pub fn write_word_array(&mut self, values: &[impl Display]) -> io::Result<()> {
+        for line in values.chunks(WORDS_PER_LINE) {
+            let args: Vec<String> = line.iter().map(|value| value.to_string()).collect();
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            self.write_directive("word", &args)?;
         }
-        writeln!(self.writer)
+        Ok(())
     }
 
-    #[allow(dead_code)]
     pub fn write_comment(&mut self, comment: &str) -> io::Result<()> {
-        writeln!(self.writer, "    # {}", comment)
+        self.lines.push(AsmLine::Comment(comment.to_string()));
+        Ok(())
     }
 
     pub fn write_blank_line(&mut self) -> io::Result<()> {
+        self.lines.push(AsmLine::Blank);
+        Ok(())
+    }
+
+    /// Writes out the lines buffered so far
+    pub fn finish(&mut self) -> io::Result<()> {
+        for line in std::mem::take(&mut self.lines) {
+            match line {
+                AsmLine::Inst(inst) => self.format_inst(&inst)?,
+                AsmLine::Label(label) => writeln!(self.writer, "{}:", label)?,
+                AsmLine::Directive(directive, args) => {
+                    write!(self.writer, "    .{}", directive)?;
+                    if !args.is_empty() {
+                        write!(self.writer, " {}", args.join(", "))?;
+                    }
+                    writeln!(self.writer)?;
+                }
+                AsmLine::Comment(comment) => writeln!(self.writer, "    # {}", comment)?,
+                AsmLine::Blank => writeln!(self.writer)?,
+            }
+        }
+        self.writer.flush()
+    }
+
+    fn format_inst(&mut self, inst: &AsmInst) -> io::Result<()> {
+        write!(self.writer, "    {}", inst.op)?;
+        let argc = inst.args.len();
+        for (i, arg) in inst.args.iter().enumerate() {
+            let separator = if i > 0 { ", " } else { " " };
+            if self.numeric_regs && !is_label_operand(&inst.op, i, argc) {
+                write!(self.writer, "{}{}", separator, to_numeric_operand(arg))?;
+            } else {
+                write!(self.writer, "{}{}", separator, arg)?;
+            }
+        }
         writeln!(self.writer)
     }
 }
+
+/// What the peephole optimization does with an instruction
+enum Peephole {
+    Keep,
+    Drop,
+    /// Replace it with a move from this register
+    Move(String),
+}
+
+/// Decides what to do with `inst` when `last` is the instruction written
+/// right before it, with no label in between
+fn peephole(last: Option<&AsmInst>, inst: &str, args: &[&str]) -> Peephole {
+    if inst == "mv" && args[0] == args[1] {
+        return Peephole::Drop;
+    }
+    let Some(AsmInst {
+        op: last_inst,
+        args: last_args,
+    }) = last
+    else {
+        return Peephole::Keep;
+    };
+    match (last_inst.as_str(), inst) {
+        // `mv a, b; mv b, a`: b already holds a
+        ("mv", "mv") if last_args[0] == args[1] && last_args[1] == args[0] => Peephole::Drop,
+        // `sw a, addr; lw b, addr`: the word at addr is still in a
+        ("sw", "lw") if last_args[1] == args[1] => {
+            if last_args[0] == args[0] {
+                Peephole::Drop
+            } else {
+                Peephole::Move(last_args[0].clone())
+            }
+        }
+        _ => Peephole::Keep,
+    }
+}
+
+/// If `inst` only loads a constant into a register (`li`, `mv` from `x0`
+/// or from a register holding a known constant), returns both
+fn loaded_constant<'a>(
+    inst: &str,
+    args: &[&'a str],
+    known: &BTreeMap<String, i32>,
+) -> Option<(&'a str, i32)> {
+    match inst {
+        "li" => Some((args[0], args[1].parse().ok()?)),
+        "mv" if args[1] == "x0" || args[1] == "zero" => Some((args[0], 0)),
+        "mv" => Some((args[0], *known.get(args[1])?)),
+        _ => None,
+    }
+}
+
+/// Whether `inst` writes the register given as its first operand.
+/// Stores, branches and jumps only read their operands.
+fn writes_first_operand(inst: &str) -> bool {
+    !matches!(inst, "sw" | "sh" | "sb" | "j" | "jr" | "ret" | "tail") && !inst.starts_with('b')
+}
+
+/// Whether the `index`-th of `argc` operands of `inst` is a symbol
+/// (function, label or global) rather than a register or immediate.
+/// Symbols are user-chosen and may collide with register names.
+fn is_label_operand(inst: &str, index: usize, argc: usize) -> bool {
+    match inst {
+        "call" | "j" | "tail" => true,
+        "la" => index == 1,
+        _ => inst.starts_with('b') && index == argc - 1,
+    }
+}
+
+/// Translates a register operand (`a0`) or a memory operand (`8(sp)`) to
+/// numeric register names. Other operands are returned unchanged.
+fn to_numeric_operand(operand: &str) -> String {
+    if let Some(reg) = abi_to_numeric(operand) {
+        return reg.to_string();
+    }
+    if let Some((offset, base)) = operand.strip_suffix(')').and_then(|s| s.split_once('(')) {
+        if let Some(reg) = abi_to_numeric(base) {
+            return format!("{}({})", offset, reg);
+        }
+    }
+    operand.to_string()
+}
+
+fn is_register(operand: &str) -> bool {
+    abi_to_numeric(operand).is_some()
+}
+
+fn abi_to_numeric(reg: &str) -> Option<&'static str> {
+    let numeric = match reg {
+        "zero" => "x0",
+        "ra" => "x1",
+        "sp" => "x2",
+        "gp" => "x3",
+        "tp" => "x4",
+        "t0" => "x5",
+        "t1" => "x6",
+        "t2" => "x7",
+        "s0" | "fp" => "x8",
+        "s1" => "x9",
+        "a0" => "x10",
+        "a1" => "x11",
+        "a2" => "x12",
+        "a3" => "x13",
+        "a4" => "x14",
+        "a5" => "x15",
+        "a6" => "x16",
+        "a7" => "x17",
+        "s2" => "x18",
+        "s3" => "x19",
+        "s4" => "x20",
+        "s5" => "x21",
+        "s6" => "x22",
+        "s7" => "x23",
+        "s8" => "x24",
+        "s9" => "x25",
+        "s10" => "x26",
+        "s11" => "x27",
+        "t3" => "x28",
+        "t4" => "x29",
+        "t5" => "x30",
+        "t6" => "x31",
+        _ => return None,
+    };
+    Some(numeric)
+}
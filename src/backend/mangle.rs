@@ -0,0 +1,21 @@
+// Koopa IR names carry a sigil: `@` for globals and functions, `%` for
+// basic blocks and locals. Assembly symbols are the names without it.
+// All sigil stripping goes through these helpers, so a change of the
+// naming scheme only has to be made here.
+
+/// Assembly symbol of a global variable or function named `@name`
+pub fn mangle_global(name: &str) -> String {
+    strip_sigil(name, '@')
+}
+
+/// Assembly label of a basic block named `%name`
+pub fn mangle_label(name: &str) -> String {
+    strip_sigil(name, '%')
+}
+
+/// Removes the leading sigil only, leaving the rest of the name intact
+fn strip_sigil(name: &str, sigil: char) -> String {
+    name.strip_prefix(sigil)
+        .unwrap_or_else(|| panic!("Expected '{}' at the start of name '{}'", sigil, name))
+        .to_string()
+}
@@ -1,12 +1,113 @@
 mod riscv_generator;
 mod asm_writer;
+mod mangle;
+mod reg_alloc;
 mod stack_frame;
 
+use crate::stats::Stats;
 use koopa::ir::Program;
 use riscv_generator::RiscvGenerator;
 use std::io;
 
-pub fn emit_riscv(program: &Program, writer: impl io::Write) -> io::Result<()> {
-    let mut generator = RiscvGenerator::new(program, writer);
-    generator.generate_program()
+/// Calling convention of the generated code
+#[derive(Debug, Clone, Copy, Default)]
+pub enum TargetAbi {
+    /// Integer-only ILP32
+    #[default]
+    Ilp32,
+    /// ILP32 with single-precision floats passed in `fa` registers.
+    /// SysY programs have no floats yet, so the code is the same as for
+    /// `Ilp32`, but it requires the F extension
+    Ilp32f,
+}
+
+impl TargetAbi {
+    /// Parses an ABI name as accepted by `-mabi`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ilp32" => Some(TargetAbi::Ilp32),
+            "ilp32f" => Some(TargetAbi::Ilp32f),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TargetAbi::Ilp32 => "ilp32",
+            TargetAbi::Ilp32f => "ilp32f",
+        }
+    }
+}
+
+/// Options controlling RISC-V code generation
+#[derive(Debug, Clone, Default)]
+pub struct BackendOptions {
+    /// Print the stack frame layout of every function to stderr
+    pub dump_frames: bool,
+    /// Show the raw (word-aligned) frame sizes in the frame dump instead of
+    /// the 16-byte aligned ones. Debug-only: the emitted code always uses
+    /// the 16-byte aligned frame required by the ABI
+    pub dump_unrounded_frames: bool,
+    /// Print the name, type and size of every global to stderr, comparing
+    /// the declared size with the bytes actually emitted
+    pub dump_data_layout: bool,
+    /// Use numeric register names (`x10`) instead of ABI names (`a0`)
+    pub numeric_regs: bool,
+    /// Annotate the assembly with comments describing each stack frame and
+    /// giving the Koopa IR instruction each group of instructions comes from
+    pub comments: bool,
+    /// Emit `.globl` only for `main`, keeping the other functions local
+    /// to the assembly file
+    pub local_functions: bool,
+    /// Reuse registers that already hold a constant instead of loading it
+    /// again (enabled by `-perf`)
+    pub reuse_constants: bool,
+    /// Keep short-lived instruction results in registers instead of
+    /// spilling every result to the stack (enabled by `-perf`)
+    pub allocate_registers: bool,
+    /// Replace multiplications by 0, 1, 2^k and 2^k + 1 with moves, shifts
+    /// and adds (enabled by `-perf`)
+    pub strength_reduction: bool,
+    /// Leave out redundant moves and reloads of a word just stored (enabled
+    /// by `-perf`)
+    pub peephole: bool,
+    /// Target plain RV32I without the M extension: multiplications by a
+    /// constant become shifts and adds, any other multiplication, division
+    /// or remainder is rejected
+    pub no_m_ext: bool,
+    /// Largest stack frame in bytes that is accepted silently, defaulting
+    /// to `DEFAULT_MAX_FRAME_SIZE`. Larger frames are warned about
+    pub max_frame_size: Option<i32>,
+    /// Reject frames larger than `max_frame_size` instead of warning
+    pub frame_size_error: bool,
+    /// Print the registers written by every function to stderr
+    pub emit_clobbers: bool,
+    /// ABI the output is declared for
+    pub target_abi: TargetAbi,
+    /// Keep a frame pointer in `s0`/`fp` pointing at the top of the frame
+    /// and address locals from it instead of from `sp`
+    pub frame_pointer: bool,
+}
+
+/// Generates RISC-V assembly for `program` into `writer`, recording its
+/// size in `stats`.
+/// The output always ends with exactly one newline
+pub fn emit_riscv(
+    program: &Program,
+    mut writer: impl io::Write,
+    options: &BackendOptions,
+    stats: &mut Stats,
+) -> io::Result<()> {
+    let mut asm = Vec::new();
+    let mut generator = RiscvGenerator::new(program, &mut asm, options.clone());
+    generator.generate_program()?;
+    generator.finish()?;
+    stats.riscv_insts = Some(generator.inst_count());
+    stats.max_frame_size = Some(generator.max_frame_size());
+
+    while asm.last() == Some(&b'\n') {
+        asm.pop();
+    }
+    asm.push(b'\n');
+    writer.write_all(&asm)
 }
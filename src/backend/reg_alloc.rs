@@ -0,0 +1,126 @@
+use koopa::ir::entities::*;
+use std::collections::{BTreeSet, HashMap};
+
+/// Registers that can hold instruction results across instructions: the
+/// argument registers, which the code generator itself only uses to pass
+/// arguments and return values, then the callee-saved registers except
+/// `s0`, the frame pointer. t0-t6 are scratch and constant registers
+const REGS: [&str; 19] = [
+    "a0", "a1", "a2", "a3", "a4", "a5", "a6", "a7", "s1", "s2", "s3", "s4", "s5", "s6", "s7", "s8",
+    "s9", "s10", "s11",
+];
+/// Number of argument registers at the start of `REGS`
+const ARG_REGS: usize = 8;
+
+/// Assigns registers to the short-lived results of `func`, so that they
+/// are never written to the stack.
+///
+/// A result is kept in a register if all of its uses are in its own basic
+/// block. Results that must survive a call between their definition and
+/// their last use (being a call argument counts, as a0-a7 are filled one
+/// by one) get a callee-saved register, the others preferably an argument
+/// register.
+/// Within each block registers are handed out in a linear scan and reused
+/// once their value is dead. When none is free, the result keeps its stack
+/// slot.
+///
+/// The registers receiving the function's own parameters are left alone
+/// until the incoming arguments have been read from them.
+pub fn allocate_registers(func: &FunctionData) -> HashMap<Value, &'static str> {
+    let param_regs = func.params().len().min(ARG_REGS);
+    let arg_reads = incoming_arg_reads(func);
+
+    let mut registers = HashMap::new();
+    for (&bb, node) in func.layout().bbs() {
+        let insts: Vec<Value> = node.insts().keys().copied().collect();
+        let index: HashMap<Value, usize> = insts.iter().enumerate().map(|(i, &v)| (v, i)).collect();
+        let is_call = |i: usize| matches!(func.dfg().value(insts[i]).kind(), ValueKind::Call(_));
+
+        // The parameter registers become free after the last instruction
+        // of this block reading one, if no other block reads them
+        let reserved_until = match arg_reads.as_slice() {
+            [] => None,
+            [(read_bb, last_read)] if *read_bb == bb => Some(*last_read),
+            [(_, _)] => None,
+            _ => Some(usize::MAX),
+        };
+        // Indices into `REGS`, so that the lowest free register is picked,
+        // for readable output
+        let mut free: BTreeSet<usize> = match reserved_until {
+            Some(_) => (param_regs..REGS.len()).collect(),
+            None => (0..REGS.len()).collect(),
+        };
+        let mut active: Vec<(usize, usize)> = Vec::new(); // (last use, register)
+        for (i, &inst) in insts.iter().enumerate() {
+            if reserved_until.is_some_and(|last_read| last_read + 1 == i) {
+                free.extend(0..param_regs);
+            }
+            // Operands whose last use is this instruction are read before
+            // its result is written, so their registers can be reused
+            active.retain(|&(last_use, reg)| {
+                if last_use <= i {
+                    free.insert(reg);
+                }
+                last_use > i
+            });
+
+            let Some(last_use) = block_local_last_use(func, bb, &index, inst) else {
+                continue;
+            };
+            let candidates = if (i + 1..=last_use).any(is_call) {
+                ARG_REGS..REGS.len()
+            } else {
+                0..REGS.len()
+            };
+            let Some(&reg) = free.range(candidates).next() else {
+                continue;
+            };
+            free.remove(&reg);
+            registers.insert(inst, REGS[reg]);
+            active.push((last_use, reg));
+        }
+    }
+    registers
+}
+
+/// Returns, for every basic block reading incoming arguments from a0-a7,
+/// the index of its last instruction doing so
+fn incoming_arg_reads(func: &FunctionData) -> Vec<(BasicBlock, usize)> {
+    let mut last_reads: Vec<(BasicBlock, usize)> = Vec::new();
+    for &param in func.params().iter().take(ARG_REGS) {
+        for &user in func.dfg().value(param).used_by() {
+            let bb = func.layout().parent_bb(user).unwrap();
+            let position = func.layout().bbs().node(&bb).unwrap().insts().keys();
+            let i = position.take_while(|&&inst| inst != user).count();
+            match last_reads.iter_mut().find(|(read_bb, _)| *read_bb == bb) {
+                Some((_, last_read)) => *last_read = (*last_read).max(i),
+                None => last_reads.push((bb, i)),
+            }
+        }
+    }
+    last_reads
+}
+
+/// Returns the index of the last use of the result of `inst` in block
+/// `bb`, or None if it has no result that fits in a register or is used
+/// outside of `bb`. Unused results count as used by `inst` itself
+fn block_local_last_use(
+    func: &FunctionData,
+    bb: BasicBlock,
+    index: &HashMap<Value, usize>,
+    inst: Value,
+) -> Option<usize> {
+    let data = func.dfg().value(inst);
+    // Allocs are addresses in the frame, not values computed into a register
+    if data.ty().is_unit() || matches!(data.kind(), ValueKind::Alloc(_)) {
+        return None;
+    }
+    let mut last_use = index[&inst];
+    for &user in data.used_by() {
+        if func.layout().parent_bb(user) != Some(bb) {
+            return None;
+        }
+        last_use = last_use.max(index[&user]);
+    }
+    Some(last_use)
+}
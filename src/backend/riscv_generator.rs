@@ -1,30 +1,92 @@
 use crate::backend::asm_writer::AsmWriter;
-use crate::backend::stack_frame::StackFrame;
+use crate::backend::mangle::{mangle_global, mangle_label};
+use crate::backend::reg_alloc::allocate_registers;
+use crate::backend::stack_frame::{StackFrame, DEFAULT_MAX_FRAME_SIZE};
+use crate::backend::{BackendOptions, TargetAbi};
+use koopa::back::KoopaGenerator;
 use koopa::ir::entities::*;
 use koopa::ir::{values::BinaryOp as KoopaBinaryOp, *};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::io::{self, Write};
 
 pub const WORD_SIZE: i32 = 4;
 const MAX_IMM_12: i32 = 2047; // Maximum positive immediate for 12-bit signed integer
+// Reach of conditional branches (`bnez`) in bytes, in either direction
+const BRANCH_RANGE: usize = 4096;
+// Largest size of what is counted as one instruction: pseudo-instructions
+// such as `li` of a large value or `call` expand to two
+const MAX_INST_SIZE: usize = 8;
 
 pub struct RiscvGenerator<'a, W: Write> {
     program: &'a Program,
     writer: AsmWriter<W>,
+    options: BackendOptions,
+    max_frame_size: i32, // Largest stack frame generated so far
+    // Koopa IR text of every instruction, written before its expansion.
+    // Only filled with `--comments`
+    koopa_lines: HashMap<Value, String>,
 }
 
 impl<'a, W: Write> RiscvGenerator<'a, W> {
-    pub fn new(program: &'a Program, writer: W) -> Self {
+    pub fn new(program: &'a Program, writer: W, options: BackendOptions) -> Self {
+        let mut writer = AsmWriter::new(writer);
+        writer.set_numeric_regs(options.numeric_regs);
+        writer.set_reuse_constants(options.reuse_constants);
+        writer.set_peephole(options.peephole);
         Self {
             program,
-            writer: AsmWriter::new(writer),
+            writer,
+            options,
+            max_frame_size: 0,
+            koopa_lines: HashMap::new(),
         }
     }
 
+    /// Number of instructions emitted so far
+    pub fn inst_count(&self) -> usize {
+        self.writer.inst_count()
+    }
+
+    /// Largest stack frame among the functions generated so far
+    pub fn max_frame_size(&self) -> i32 {
+        self.max_frame_size
+    }
+
+    /// Writes out the assembly generated so far
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.writer.finish()
+    }
+
     pub fn generate_program(&mut self) -> io::Result<()> {
         let program = self.program;
+        if self.options.comments {
+            self.koopa_lines = koopa_inst_lines(program)?;
+        }
+
+        // Declare the ISA extensions the code relies on. The ABI itself is
+        // an ELF header flag chosen by the assembler's `-mabi`, there is no
+        // directive for it, so it is only recorded in a comment
+        let m_ext = if self.options.no_m_ext {
+            self.check_no_m_ext()?;
+            ""
+        } else {
+            "m"
+        };
+        let f_ext = match self.options.target_abi {
+            TargetAbi::Ilp32 => "",
+            TargetAbi::Ilp32f => "f",
+        };
+        let arch = format!("\"rv32i{}{}\"", m_ext, f_ext);
+        self.writer.write_directive("attribute", &["arch", &arch])?;
+        self.writer
+            .write_comment(&format!("abi: {}", self.options.target_abi.name()))?;
 
-        // Generate data segment for global variables
-        self.writer.write_directive("data", &[])?;
+        // Generate data segment for global variables. Sections without
+        // contents are left out entirely
+        let has_globals = !program.inst_layout().is_empty();
+        if has_globals {
+            self.writer.write_directive("data", &[])?;
+        }
         for &global in program.inst_layout() {
             let name = self.get_global_value_name(global);
 
@@ -36,51 +98,209 @@ impl<'a, W: Write> RiscvGenerator<'a, W> {
                 unreachable!("Expected GlobalAlloc for global variable");
             };
             // Initialization
-            self.generate_global_init(alloc.init())?;
+            let emitted_size = self.generate_global_init(alloc.init())?;
+            if self.options.dump_data_layout {
+                let ty = self.get_global_value_type(alloc.init());
+                let mismatch = if ty.size() == emitted_size { "" } else { " (MISMATCH)" };
+                eprintln!(
+                    "global {}: {}, declared {} bytes, emitted {} bytes{}",
+                    name,
+                    ty,
+                    ty.size(),
+                    emitted_size,
+                    mismatch
+                );
+            }
+        }
+        if has_globals {
+            self.writer.write_blank_line()?;
         }
-        self.writer.write_blank_line()?;
 
-        // Generate text segment for functions
-        self.writer.write_directive("text", &[])?;
+        // Generate text segment for functions, in source order like the
+        // Koopa IR output
+        let has_functions = program
+            .func_layout()
+            .iter()
+            .any(|&func| program.func(func).layout().entry_bb().is_some());
+        if has_functions {
+            self.writer.write_directive("text", &[])?;
+        }
         for &func in program.func_layout() {
             let func_data = program.func(func);
             // Skip function declarations (none entry basic block)
             if func_data.layout().entry_bb().is_none() {
                 continue;
             }
+            let far_branches = self.find_far_branches(func_data)?;
             let mut func_gen = FunctionGenerator::new(self, func_data);
+            func_gen.far_branches = far_branches;
+            if func_gen.gen.options.dump_frames {
+                let rounded = !func_gen.gen.options.dump_unrounded_frames;
+                eprint!("{}", func_gen.stack_frame.dump(func_data, rounded));
+            }
+            let max_frame_size = func_gen
+                .gen
+                .options
+                .max_frame_size
+                .unwrap_or(DEFAULT_MAX_FRAME_SIZE);
+            if let Err(message) = func_gen.stack_frame.check_size(func_data, max_frame_size) {
+                if func_gen.gen.options.frame_size_error {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+                }
+                eprintln!("warning: {}", message);
+            }
+            let frame_size = func_gen.stack_frame.get_stack_size();
+            func_gen.gen.max_frame_size = func_gen.gen.max_frame_size.max(frame_size);
             func_gen.generate_function()?;
+
+            let written_regs = self.writer.take_written_regs();
+            if self.options.emit_clobbers {
+                eprintln!("{}", clobber_report(func_data, &written_regs));
+            }
         }
         Ok(())
     }
 
-    fn generate_global_init(&mut self, init: Value) -> io::Result<()> {
+    /// Returns the conditional branches of `func` whose true target may be
+    /// out of the range of `bnez`.
+    /// The function is generated once without output, with every branch in
+    /// the long form, to measure the distances. Emitting some of them in
+    /// the short form afterwards only brings the targets closer, so the
+    /// others stay in range
+    fn find_far_branches(&self, func: &FunctionData) -> io::Result<HashSet<Value>> {
+        let mut dry_run = RiscvGenerator::new(self.program, io::sink(), self.options.clone());
+        let mut func_gen = FunctionGenerator::new(&mut dry_run, func);
+        func_gen.far_branches = func_gen.branches().collect();
+        func_gen.generate_function()?;
+        let branch_sites = std::mem::take(&mut func_gen.branch_sites);
+
+        let writer = &dry_run.writer;
+        let far_branches = branch_sites
+            .into_iter()
+            .filter(|(_, position, target)| {
+                let target = writer.label_position(target).unwrap();
+                target.abs_diff(*position) * MAX_INST_SIZE >= BRANCH_RANGE
+            })
+            .map(|(branch, _, _)| branch)
+            .collect();
+        Ok(far_branches)
+    }
+
+    /// Emits the data directives for a global initializer and returns the
+    /// number of bytes they occupy
+    fn generate_global_init(&mut self, init: Value) -> io::Result<usize> {
         let kind = self.get_global_value_kind(init);
         let ty = self.get_global_value_type(init);
         match kind {
-            ValueKind::Integer(int) => self
-                .writer
-                .write_directive("word", &[&int.value().to_string()]),
-            ValueKind::ZeroInit(_) => self
-                .writer
-                .write_directive("zero", &[&ty.size().to_string()]),
+            ValueKind::Integer(int) => {
+                self.writer
+                    .write_directive("word", &[&int.value().to_string()])?;
+                Ok(WORD_SIZE as usize)
+            }
+            ValueKind::ZeroInit(_) => {
+                self.writer
+                    .write_directive("zero", &[&ty.size().to_string()])?;
+                Ok(ty.size())
+            }
+            // Nested aggregates are flattened, so that the zeros at the end
+            // of the whole array become a single `.zero`. Runs of words are
+            // written several per directive
+            ValueKind::Aggregate(_) => {
+                let mut words = Vec::new();
+                self.flatten_global_init(init, &mut words)?;
+                let zeros_from = words
+                    .iter()
+                    .rposition(|word| !word.is_zero())
+                    .map_or(0, |last| last + 1);
+                let mut run: Vec<&str> = Vec::new();
+                for word in &words[..zeros_from] {
+                    match word {
+                        DataWord::Word(word) => run.push(word),
+                        DataWord::Zero(size) => {
+                            self.writer.write_word_array(&run)?;
+                            run.clear();
+                            self.writer.write_directive("zero", &[&size.to_string()])?
+                        }
+                    }
+                }
+                self.writer.write_word_array(&run)?;
+                let trailing_zeros: usize = words[zeros_from..].iter().map(DataWord::size).sum();
+                if trailing_zeros > 0 {
+                    self.writer
+                        .write_directive("zero", &[&trailing_zeros.to_string()])?;
+                }
+                Ok(words.iter().map(DataWord::size).sum())
+            }
+            // The address of another global, e.g. in a pointer global
+            ValueKind::GlobalAlloc(_) => {
+                let symbol = self.get_global_value_name(init);
+                self.writer.write_directive("word", &[&symbol])?;
+                Ok(WORD_SIZE as usize)
+            }
+            _ => Err(unsupported_global_init(&kind)),
+        }
+    }
+
+    /// Appends the data of a global initializer to `words`, element by
+    /// element
+    fn flatten_global_init(&self, init: Value, words: &mut Vec<DataWord>) -> io::Result<()> {
+        let kind = self.get_global_value_kind(init);
+        match kind {
+            ValueKind::Integer(int) => words.push(DataWord::Word(int.value().to_string())),
+            ValueKind::ZeroInit(_) => {
+                words.push(DataWord::Zero(self.get_global_value_type(init).size()))
+            }
             ValueKind::Aggregate(agg) => {
                 for &elem in agg.elems() {
-                    self.generate_global_init(elem)?;
+                    self.flatten_global_init(elem, words)?;
                 }
-                Ok(())
             }
-            _ => unreachable!("Unsupported global initializer"),
+            ValueKind::GlobalAlloc(_) => {
+                words.push(DataWord::Word(self.get_global_value_name(init)))
+            }
+            _ => return Err(unsupported_global_init(&kind)),
         }
+        Ok(())
+    }
+
+    /// Checks that every multiplication, division and remainder can be
+    /// lowered without the M extension
+    fn check_no_m_ext(&self) -> io::Result<()> {
+        for &func in self.program.func_layout() {
+            let func_data = self.program.func(func);
+            for (_, node) in func_data.layout().bbs() {
+                for &inst in node.insts().keys() {
+                    let ValueKind::Binary(bin) = func_data.dfg().value(inst).kind() else {
+                        continue;
+                    };
+                    let is_integer = |value: Value| {
+                        matches!(func_data.dfg().value(value).kind(), ValueKind::Integer(_))
+                    };
+                    let operation = match bin.op() {
+                        KoopaBinaryOp::Mul if is_integer(bin.lhs()) || is_integer(bin.rhs()) => {
+                            continue
+                        }
+                        KoopaBinaryOp::Mul => "multiplication of two non-constant values",
+                        KoopaBinaryOp::Div => "division",
+                        KoopaBinaryOp::Mod => "remainder",
+                        _ => continue,
+                    };
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "{} in function '{}' requires the M extension, which --no-m-ext disables",
+                            operation,
+                            mangle_global(func_data.name())
+                        ),
+                    ));
+                }
+            }
+        }
+        Ok(())
     }
 
     fn get_global_value_name(&self, value: Value) -> String {
-        self.program
-            .borrow_value(value)
-            .name()
-            .as_ref()
-            .unwrap()
-            .replace("@", "")
+        mangle_global(self.program.borrow_value(value).name().as_ref().unwrap())
     }
 
     fn get_global_value_kind(&self, value: Value) -> ValueKind {
@@ -92,32 +312,129 @@ impl<'a, W: Write> RiscvGenerator<'a, W> {
     }
 }
 
+/// One data directive of a flattened global initializer
+enum DataWord {
+    /// `.word`, with a value or a symbol
+    Word(String),
+    /// `.zero` of the given number of bytes
+    Zero(usize),
+}
+
+impl DataWord {
+    fn is_zero(&self) -> bool {
+        match self {
+            DataWord::Word(word) => word == "0",
+            DataWord::Zero(_) => true,
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            DataWord::Word(_) => WORD_SIZE as usize,
+            DataWord::Zero(size) => *size,
+        }
+    }
+}
+
+fn unsupported_global_init(kind: &ValueKind) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("unsupported global initializer: {:?}", kind),
+    )
+}
+
+/// Maps every instruction of `program` to its line in the Koopa IR text,
+/// with the value names `-koopa` shows
+fn koopa_inst_lines(program: &Program) -> io::Result<HashMap<Value, String>> {
+    let mut text = Vec::new();
+    KoopaGenerator::new(&mut text).generate_on(program)?;
+    let text = String::from_utf8_lossy(&text);
+    // Instructions are the only indented lines, in layout order
+    let mut lines = text.lines().filter(|line| line.starts_with(' '));
+    let mut inst_lines = HashMap::new();
+    for &func in program.func_layout() {
+        for node in program.func(func).layout().bbs().nodes() {
+            for &inst in node.insts().keys() {
+                let line = lines.next().expect("missing instruction in Koopa IR text");
+                inst_lines.insert(inst, line.trim().to_string());
+            }
+        }
+    }
+    Ok(inst_lines)
+}
+
+/// Registers the code generator does not use otherwise. With
+/// `reuse_constants`, constant operands are kept in them, round-robin
+const CONSTANT_REGS: [&str; 3] = ["t4", "t5", "t6"];
+
 struct FunctionGenerator<'a, 'b, W: Write> {
     gen: &'a mut RiscvGenerator<'b, W>,
     func: &'b FunctionData,
     stack_frame: StackFrame,
+    // Results kept in a register instead of a stack slot, see
+    // `allocate_registers`
+    registers: HashMap<Value, &'static str>,
+    next_constant_reg: usize, // Index into CONSTANT_REGS
+    // Label of the shared epilogue that returns jump to, if the function
+    // has several returns
+    epilogue_label: Option<String>,
+    // Conditional branches emitted in the long form, see
+    // `RiscvGenerator::find_far_branches`
+    far_branches: HashSet<Value>,
+    // (branch, instructions written before it, label of its true target)
+    // of the conditional branches generated so far
+    branch_sites: Vec<(Value, usize, String)>,
 }
 
 impl<'a, 'b, W: Write> FunctionGenerator<'a, 'b, W> {
     pub fn new(riscv_gen: &'a mut RiscvGenerator<'b, W>, func: &'b FunctionData) -> Self {
+        let registers = if riscv_gen.options.allocate_registers {
+            allocate_registers(func)
+        } else {
+            HashMap::new()
+        };
         let mut stack_frame = StackFrame::new();
-        stack_frame.initialize(func);
+        stack_frame.initialize(func, riscv_gen.options.frame_pointer, &registers);
         Self {
             gen: riscv_gen,
             func,
             stack_frame,
+            registers,
+            next_constant_reg: 0,
+            epilogue_label: None,
+            far_branches: HashSet::new(),
+            branch_sites: Vec::new(),
         }
     }
 
+    /// The conditional branches of the function
+    fn branches(&self) -> impl Iterator<Item = Value> + '_ {
+        self.func
+            .layout()
+            .bbs()
+            .nodes()
+            .flat_map(|node| node.insts().keys().copied())
+            .filter(|&inst| matches!(self.func.dfg().value(inst).kind(), ValueKind::Branch(_)))
+    }
+
     fn generate_function(&mut self) -> io::Result<()> {
-        // Function name starts with an '@'
-        let name = self.func.name().replace("@", "");
-        self.gen.writer.write_directive("globl", &[&name])?;
+        let name = mangle_global(self.func.name());
+        if !self.gen.options.local_functions || name == "main" {
+            self.gen.writer.write_directive("globl", &[&name])?;
+        }
         self.gen.writer.write_label(&name)?;
 
+        // Functions with a single return keep the epilogue inline, the
+        // others share one to avoid duplicating it
+        if self.count_returns() > 1 {
+            self.epilogue_label = Some(format!(".{}_epilogue", name));
+        }
+
         // Stack frame setup
         self.generate_prologue()?;
         self.save_caller_saved_regs()?;
+        self.save_callee_saved_regs()?;
+        self.setup_frame_pointer()?;
 
         // Generate code for each basic block
         let mut is_first_bb = true;
@@ -133,12 +450,46 @@ impl<'a, 'b, W: Write> FunctionGenerator<'a, 'b, W> {
 
             // Generate code for each instruction in the basic block
             for &inst in node.insts().keys() {
+                if let Some(line) = self.gen.koopa_lines.get(&inst) {
+                    self.gen.writer.write_comment(line)?;
+                }
                 self.generate_instruction(inst)?;
             }
         }
+
+        if let Some(label) = self.epilogue_label.clone() {
+            self.gen.writer.write_label(&label)?;
+            self.generate_return()?;
+        }
         Ok(())
     }
 
+    fn count_returns(&self) -> usize {
+        self.func
+            .layout()
+            .bbs()
+            .nodes()
+            .flat_map(|node| node.insts().keys())
+            .filter(|&&inst| matches!(self.func.dfg().value(inst).kind(), ValueKind::Return(_)))
+            .count()
+    }
+
+    /// Whether `inst` is the last instruction emitted for the function
+    fn is_last_inst(&self, inst: Value) -> bool {
+        let last_bb = self.func.layout().bbs().back_key();
+        last_bb.and_then(|bb| self.func.layout().bbs().node(bb)?.insts().back_key()) == Some(&inst)
+    }
+
+    /// Restores `ra`, frees the stack frame and returns to the caller
+    fn generate_return(&mut self) -> io::Result<()> {
+        self.restore_caller_saved_regs()?;
+        self.restore_callee_saved_regs()?;
+        self.restore_frame_pointer()?;
+        self.generate_epilogue()?;
+        self.gen.writer.write_inst("ret", &[])?;
+        self.gen.writer.write_blank_line()
+    }
+
     fn generate_instruction(&mut self, value: Value) -> io::Result<()> {
         let value_kind = self.get_value_kind(value);
         match value_kind {
@@ -147,6 +498,22 @@ impl<'a, 'b, W: Write> FunctionGenerator<'a, 'b, W> {
             ValueKind::Call(call) => {
                 let args = call.args();
 
+                // Arguments are constants, globals, values in stack slots or
+                // values in callee-saved registers: `allocate_registers` puts
+                // any value whose last use is a call, arguments included, in
+                // s1-s11, and results of inner calls (e.g. `g(x)` in
+                // `f(g(x), y)`) are saved right after their own `call`. So
+                // filling a0-a7 in order cannot clobber a pending value.
+                // Incoming arguments would break this, as they are read
+                // from a0-a7 themselves; the frontend always copies them
+                // into allocs first.
+                debug_assert!(
+                    args.iter().all(|&arg| {
+                        !matches!(self.get_value_kind(arg), ValueKind::FuncArgRef(a) if a.index() < 8)
+                    }),
+                    "Call arguments must not read incoming argument registers directly"
+                );
+
                 // Load arguments into regs or stack
                 for (i, &arg) in args.iter().enumerate() {
                     if i < 8 {
@@ -154,14 +521,14 @@ impl<'a, 'b, W: Write> FunctionGenerator<'a, 'b, W> {
                     } else {
                         self.load_value_to_reg(arg, "t0", "t3")?;
                         let offset = (i as i32 - 8) * WORD_SIZE;
-                        let addr = self.build_stk_addr_str(offset, "t1")?;
+                        let addr = self.build_sp_addr_str(offset, "t1")?;
                         self.gen.writer.write_inst("sw", &["t0", &addr])?;
                     }
                 }
 
                 // Call the function
                 let callee = call.callee();
-                let callee_name = self.gen.program.func(callee).name().replace("@", "");
+                let callee_name = mangle_global(self.gen.program.func(callee).name());
                 self.gen.writer.write_inst("call", &[&callee_name])?;
 
                 // Save return value if there is one
@@ -176,42 +543,70 @@ impl<'a, 'b, W: Write> FunctionGenerator<'a, 'b, W> {
                 if let Some(ret_value) = ret.value() {
                     self.load_value_to_reg(ret_value, "a0", "t3")?;
                 }
-                self.restore_caller_saved_regs()?;
-                self.generate_epilogue()?;
-                self.gen.writer.write_inst("ret", &[])?;
-                self.gen.writer.write_blank_line()?;
+                match &self.epilogue_label {
+                    // The last return falls through into the epilogue
+                    Some(_) if self.is_last_inst(value) => {}
+                    Some(label) => self.gen.writer.write_inst("j", &[label])?,
+                    None => self.generate_return()?,
+                }
             }
 
             ValueKind::Binary(bin) => {
-                self.load_value_to_reg(bin.lhs(), "t0", "t3")?;
-                self.load_value_to_reg(bin.rhs(), "t1", "t3")?;
+                if let Some((operand, multiplier)) = self.reducible_mul(&bin) {
+                    let src = self.load_operand(operand, "t0")?;
+                    let dest = self.result_reg(value);
+                    self.write_reduced_mul(&dest, &src, multiplier)?;
+                    return self.save_value_from_reg(value, &dest, "t3");
+                }
+
+                let lhs = self.load_operand(bin.lhs(), "t0")?;
+                let rhs = self.load_operand(bin.rhs(), "t1")?;
+                let (lhs, rhs) = (lhs.as_str(), rhs.as_str());
+                let dest = self.result_reg(value);
+                let dest = dest.as_str();
 
                 let op_str = map_binary_op(bin.op());
+                // Comparisons always materialize 0 or 1, so their results
+                // can be stored like any other value, not only branched on
                 match bin.op() {
                     KoopaBinaryOp::Le => {
-                        self.gen.writer.write_inst("sgt", &["t0", "t0", "t1"])?; // t0 = (lhs > rhs)
-                        self.gen.writer.write_inst("seqz", &["t0", "t0"])?; // t0 = (t0 == 0) => !(lhs > rhs) => lhs <= rhs
+                        self.gen.writer.write_inst("sgt", &[dest, lhs, rhs])?; // t0 = (lhs > rhs)
+                        self.gen.writer.write_inst("seqz", &[dest, dest])?; // t0 = (t0 == 0) => !(lhs > rhs) => lhs <= rhs
                     }
                     KoopaBinaryOp::Ge => {
-                        self.gen.writer.write_inst("slt", &["t0", "t0", "t1"])?;
-                        self.gen.writer.write_inst("seqz", &["t0", "t0"])?;
+                        self.gen.writer.write_inst("slt", &[dest, lhs, rhs])?;
+                        self.gen.writer.write_inst("seqz", &[dest, dest])?;
                     }
                     KoopaBinaryOp::Eq => {
-                        self.gen.writer.write_inst("xor", &["t0", "t0", "t1"])?;
-                        self.gen.writer.write_inst("seqz", &["t0", "t0"])?;
+                        self.gen.writer.write_inst("xor", &[dest, lhs, rhs])?;
+                        self.gen.writer.write_inst("seqz", &[dest, dest])?;
                     }
                     KoopaBinaryOp::NotEq => {
-                        self.gen.writer.write_inst("xor", &["t0", "t0", "t1"])?;
-                        self.gen.writer.write_inst("snez", &["t0", "t0"])?;
+                        self.gen.writer.write_inst("xor", &[dest, lhs, rhs])?;
+                        self.gen.writer.write_inst("snez", &[dest, dest])?;
+                    }
+                    // Without `mul`, one operand is a constant (see `check_no_m_ext`)
+                    KoopaBinaryOp::Mul if self.gen.options.no_m_ext => {
+                        match self.get_value_kind(bin.rhs()) {
+                            ValueKind::Integer(int) => {
+                                self.write_mul_const(dest, lhs, int.value())?
+                            }
+                            _ => {
+                                let ValueKind::Integer(int) = self.get_value_kind(bin.lhs()) else {
+                                    unreachable!("Multiplication without a constant operand");
+                                };
+                                self.write_mul_const(dest, rhs, int.value())?
+                            }
+                        }
                     }
                     _ => {
                         // Regular binary operations
                         if let Some(op) = op_str {
-                            self.gen.writer.write_inst(op, &["t0", "t0", "t1"])?;
+                            self.gen.writer.write_inst(op, &[dest, lhs, rhs])?;
                         }
                     }
                 }
-                self.save_value_from_reg(value, "t0", "t3")?;
+                self.save_value_from_reg(value, dest, "t3")?;
             }
 
             ValueKind::Alloc(_) => {
@@ -252,9 +647,12 @@ impl<'a, 'b, W: Write> FunctionGenerator<'a, 'b, W> {
             }
 
             ValueKind::Load(load) => {
-                self.load_value_to_reg(load.src(), "t0", "t3")?;
-                self.gen.writer.write_inst("lw", &["t0", "0(t0)"])?;
-                self.save_value_from_reg(value, "t0", "t3")?;
+                let dest = self.result_reg(value);
+                let src = self.load_operand(load.src(), "t0")?;
+                self.gen
+                    .writer
+                    .write_inst("lw", &[&dest, &format!("0({})", src)])?;
+                self.save_value_from_reg(value, &dest, "t3")?;
             }
 
             ValueKind::Branch(branch) => {
@@ -265,7 +663,20 @@ impl<'a, 'b, W: Write> FunctionGenerator<'a, 'b, W> {
                 self.load_value_to_reg(cond, "t0", "t3")?;
                 let true_bb_name = self.get_bb_name(true_bb);
                 let false_bb_name = self.get_bb_name(false_bb);
-                self.gen.writer.write_inst("bnez", &["t0", &true_bb_name])?;
+                let position = self.gen.writer.inst_count();
+                self.branch_sites
+                    .push((value, position, true_bb_name.clone()));
+                if self.far_branches.contains(&value) {
+                    // `bnez` only reaches 4 KiB away, `j` 1 MiB: branch
+                    // over a jump to the true target instead
+                    let parent = self.func.layout().parent_bb(value).unwrap();
+                    let skip_label = format!("{}_far", self.get_bb_name(parent));
+                    self.gen.writer.write_inst("beqz", &["t0", &skip_label])?;
+                    self.gen.writer.write_inst("j", &[&true_bb_name])?;
+                    self.gen.writer.write_label(&skip_label)?;
+                } else {
+                    self.gen.writer.write_inst("bnez", &["t0", &true_bb_name])?;
+                }
                 self.gen.writer.write_inst("j", &[&false_bb_name])?;
             }
 
@@ -300,6 +711,8 @@ impl<'a, 'b, W: Write> FunctionGenerator<'a, 'b, W> {
                 self.gen
                     .writer
                     .write_inst("slli", &["t1", "t1", &shift.to_string()])?;
+            } else if self.gen.options.no_m_ext {
+                self.write_mul_const("t1", "t1", step as i32)?;
             } else {
                 self.gen
                     .writer
@@ -307,8 +720,84 @@ impl<'a, 'b, W: Write> FunctionGenerator<'a, 'b, W> {
                 self.gen.writer.write_inst("mul", &["t1", "t1", "t2"])?;
             }
         }
-        self.gen.writer.write_inst("add", &["t0", "t0", "t1"])?;
-        self.save_value_from_reg(dest, "t0", "t3")
+        let result = self.result_reg(dest);
+        self.gen.writer.write_inst("add", &[&result, "t0", "t1"])?;
+        self.save_value_from_reg(dest, &result, "t3")
+    }
+
+    /// With `strength_reduction`, matches a multiplication of a value by a
+    /// constant that `write_reduced_mul` handles, returning the value and
+    /// the constant
+    fn reducible_mul(&self, bin: &values::Binary) -> Option<(Value, i32)> {
+        if !self.gen.options.strength_reduction || bin.op() != KoopaBinaryOp::Mul {
+            return None;
+        }
+        let constant = |value: Value| match self.get_value_kind(value) {
+            ValueKind::Integer(int) if !value.is_global() => Some(int.value()),
+            _ => None,
+        };
+        let (operand, multiplier) = match (constant(bin.lhs()), constant(bin.rhs())) {
+            (_, Some(c)) => (bin.lhs(), c),
+            (Some(c), None) => (bin.rhs(), c),
+            (None, None) => return None,
+        };
+        let is_power_of_two = |c: i32| c > 0 && (c as u32).is_power_of_two();
+        let reducible = multiplier == 0
+            || is_power_of_two(multiplier)
+            || (multiplier > 2 && is_power_of_two(multiplier - 1));
+        reducible.then_some((operand, multiplier))
+    }
+
+    /// `dest = src * multiplier` for the constants accepted by
+    /// `reducible_mul`: `x * 8` is `slli x, 3`, `x * 9` is `(x << 3) + x`.
+    /// Uses t2 as a scratch register
+    fn write_reduced_mul(&mut self, dest: &str, src: &str, multiplier: i32) -> io::Result<()> {
+        match multiplier {
+            0 => self.gen.writer.write_inst("mv", &[dest, "x0"]),
+            1 => self.write_move(dest, src),
+            _ if (multiplier as u32).is_power_of_two() => {
+                let shift = multiplier.trailing_zeros().to_string();
+                self.gen.writer.write_inst("slli", &[dest, src, &shift])
+            }
+            _ => {
+                let shift = (multiplier - 1).trailing_zeros().to_string();
+                self.gen.writer.write_inst("slli", &["t2", src, &shift])?;
+                self.gen.writer.write_inst("add", &[dest, "t2", src])
+            }
+        }
+    }
+
+    /// `dest = src * multiplier` with shifts and adds only, for targets
+    /// without the M extension. Uses t2 and t3 as scratch registers
+    fn write_mul_const(&mut self, dest: &str, src: &str, multiplier: i32) -> io::Result<()> {
+        let magnitude = multiplier.unsigned_abs();
+        if magnitude == 0 {
+            return self.gen.writer.write_inst("mv", &[dest, "x0"]);
+        }
+        // t2 accumulates `src << k` for every set bit k of the magnitude
+        let mut first = true;
+        for bit in 0..u32::BITS {
+            if magnitude & (1 << bit) == 0 {
+                continue;
+            }
+            let target = if first { "t2" } else { "t3" };
+            if bit == 0 {
+                self.gen.writer.write_inst("mv", &[target, src])?;
+            } else {
+                self.gen
+                    .writer
+                    .write_inst("slli", &[target, src, &bit.to_string()])?;
+            }
+            if !first {
+                self.gen.writer.write_inst("add", &["t2", "t2", "t3"])?;
+            }
+            first = false;
+        }
+        if multiplier < 0 {
+            self.gen.writer.write_inst("neg", &[dest, "t2"])
+        } else {
+            self.gen.writer.write_inst("mv", &[dest, "t2"])
+        }
     }
 
     /// Allocate space for the current function's stack frame by adjusting
@@ -316,6 +805,18 @@ impl<'a, 'b, W: Write> FunctionGenerator<'a, 'b, W> {
     /// the 12-bit immediate limit.
     fn generate_prologue(&mut self) -> io::Result<()> {
         let stack_size = self.stack_frame.get_stack_size();
+        if self.gen.options.comments {
+            let ra = match self.stack_frame.get_ra_offset() {
+                Some(offset) => format!("ra at {}(sp)", offset),
+                None => "ra not saved".to_string(),
+            };
+            self.gen.writer.write_comment(&format!(
+                "frame: {} bytes, {}, locals from {}(sp)",
+                stack_size,
+                ra,
+                self.stack_frame.get_locals_offset()
+            ))?;
+        }
         if stack_size == 0 {
             return Ok(());
         }
@@ -347,17 +848,48 @@ impl<'a, 'b, W: Write> FunctionGenerator<'a, 'b, W> {
         Ok(())
     }
 
-    /// Builds a stack address string for RISC-V load/store instructions.
-    /// If the offset fits in a 12-bit immediate, it returns "offset(sp)"
+    /// Returns the base register and offset that address the frame slot
+    /// at `offset` from `sp`. With `--frame-pointer`, slots are addressed
+    /// from `s0`, which holds the value of `sp` before the prologue
+    fn frame_slot(&self, offset: i32) -> (&'static str, i32) {
+        if self.gen.options.frame_pointer {
+            ("s0", offset - self.stack_frame.get_stack_size())
+        } else {
+            ("sp", offset)
+        }
+    }
+
+    /// Builds a stack address string for RISC-V load/store instructions,
+    /// relative to the frame base (see `frame_slot`).
+    /// If the offset fits in a 12-bit immediate, it returns "offset(base)"
     /// Otherwise, it loads the offset into a temporary register and returns "0(tmp_reg)"
     fn build_stk_addr_str(&mut self, offset: i32, tmp_reg: &str) -> io::Result<String> {
-        if offset <= MAX_IMM_12 && offset >= -MAX_IMM_12 - 1 {
-            return Ok(format!("{}(sp)", offset));
+        let (base, offset) = self.frame_slot(offset);
+        self.build_addr_str(base, offset, tmp_reg)
+    }
+
+    /// Like `build_stk_addr_str`, but always relative to `sp`. Used for the
+    /// slots that are accessed while `s0` does not hold the frame base yet
+    /// (saved registers) and for outgoing arguments, which the callee
+    /// expects right at `sp`
+    fn build_sp_addr_str(&mut self, offset: i32, tmp_reg: &str) -> io::Result<String> {
+        self.build_addr_str("sp", offset, tmp_reg)
+    }
+
+    /// Returns the address `offset(base)`. An offset that does not fit in
+    /// 12 bits is added to `base` in `tmp_reg`, which must therefore not be
+    /// the register holding the value being stored (`t3` everywhere except
+    /// in the prologue and epilogue, where the values live in `ra`/`s*`)
+    fn build_addr_str(&mut self, base: &str, offset: i32, tmp_reg: &str) -> io::Result<String> {
+        if (-MAX_IMM_12 - 1..=MAX_IMM_12).contains(&offset) {
+            return Ok(format!("{}({})", offset, base));
         }
         self.gen
             .writer
             .write_inst("li", &[tmp_reg, &offset.to_string()])?;
-        self.gen.writer.write_inst("add", &[tmp_reg, "sp", tmp_reg])?;
+        self.gen
+            .writer
+            .write_inst("add", &[tmp_reg, base, tmp_reg])?;
         Ok(format!("0({})", tmp_reg))
     }
 
@@ -366,7 +898,7 @@ impl<'a, 'b, W: Write> FunctionGenerator<'a, 'b, W> {
         let Some(ra_offset) = self.stack_frame.get_ra_offset() else {
             return Ok(());
         };
-        let addr = self.build_stk_addr_str(ra_offset, "t0")?;
+        let addr = self.build_sp_addr_str(ra_offset, "t0")?;
         self.gen.writer.write_inst("sw", &["ra", &addr])
     }
 
@@ -375,18 +907,98 @@ impl<'a, 'b, W: Write> FunctionGenerator<'a, 'b, W> {
         let Some(ra_offset) = self.stack_frame.get_ra_offset() else {
             return Ok(());
         };
-        let addr = self.build_stk_addr_str(ra_offset, "t0")?;
+        let addr = self.build_sp_addr_str(ra_offset, "t0")?;
         self.gen.writer.write_inst("lw", &["ra", &addr])
     }
 
+    /// Save the callee-saved registers holding values (see
+    /// `allocate_registers`) onto the stack
+    fn save_callee_saved_regs(&mut self) -> io::Result<()> {
+        for (reg, offset) in self.stack_frame.get_callee_saved().to_vec() {
+            let addr = self.build_sp_addr_str(offset, "t0")?;
+            self.gen.writer.write_inst("sw", &[reg, &addr])?;
+        }
+        Ok(())
+    }
+
+    /// Restore the callee-saved registers saved by `save_callee_saved_regs`
+    fn restore_callee_saved_regs(&mut self) -> io::Result<()> {
+        for (reg, offset) in self.stack_frame.get_callee_saved().to_vec() {
+            let addr = self.build_sp_addr_str(offset, "t0")?;
+            self.gen.writer.write_inst("lw", &[reg, &addr])?;
+        }
+        Ok(())
+    }
+
+    /// With `--frame-pointer`, saves the caller's `s0` and points `s0` at
+    /// the top of the frame
+    fn setup_frame_pointer(&mut self) -> io::Result<()> {
+        let Some(fp_offset) = self.stack_frame.get_fp_offset() else {
+            return Ok(());
+        };
+        let addr = self.build_sp_addr_str(fp_offset, "t0")?;
+        self.gen.writer.write_inst("sw", &["s0", &addr])?;
+        let stack_size = self.stack_frame.get_stack_size();
+        if stack_size > MAX_IMM_12 {
+            self.gen
+                .writer
+                .write_inst("li", &["t0", &stack_size.to_string()])?;
+            self.gen.writer.write_inst("add", &["s0", "sp", "t0"])
+        } else {
+            self.gen
+                .writer
+                .write_inst("addi", &["s0", "sp", &stack_size.to_string()])
+        }
+    }
+
+    /// Restores the caller's `s0` saved by `setup_frame_pointer`
+    fn restore_frame_pointer(&mut self) -> io::Result<()> {
+        let Some(fp_offset) = self.stack_frame.get_fp_offset() else {
+            return Ok(());
+        };
+        let addr = self.build_sp_addr_str(fp_offset, "t0")?;
+        self.gen.writer.write_inst("lw", &["s0", &addr])
+    }
+
+    /// Returns the register holding a binary operand, loading it into `reg`
+    /// if needed. With `reuse_constants`, zero is read from `x0` and other
+    /// constants from a constant register that already holds them
+    fn load_operand(&mut self, value: Value, reg: &str) -> io::Result<String> {
+        if let Some(&held) = self.registers.get(&value) {
+            return Ok(held.to_string());
+        }
+        if self.gen.options.reuse_constants && !value.is_global() {
+            if let ValueKind::Integer(int) = self.get_value_kind(value) {
+                let constant = int.value();
+                if constant == 0 {
+                    return Ok("x0".to_string());
+                }
+                if let Some(&held) = CONSTANT_REGS
+                    .iter()
+                    .find(|reg| self.gen.writer.holds_constant(reg, constant))
+                {
+                    return Ok(held.to_string());
+                }
+                let constant_reg = CONSTANT_REGS[self.next_constant_reg];
+                self.next_constant_reg = (self.next_constant_reg + 1) % CONSTANT_REGS.len();
+                self.gen
+                    .writer
+                    .write_inst("li", &[constant_reg, &constant.to_string()])?;
+                return Ok(constant_reg.to_string());
+            }
+        }
+        self.load_value_to_reg(value, reg, "t3")?;
+        Ok(reg.to_string())
+    }
+
     /// Load a value (global or local) into a register.
     /// If the value is global, load its address using `la`.
     /// If the value is local, load it from the stack frame:
     ///   - For integer constants, use `li` (or `mv` from x0 for zero).
     ///   - For function arguments, load from `a0`-`a7` or from the stack if beyond 8 args.
     ///   - For allocated variables, compute the address from `sp` and load.
-    ///   - For other values, they should be results of other instructions and already stored on the stack.
-    ///     Load them from the stack.
+    ///   - For other values, they should be results of other instructions and already stored on the stack
+    ///     (or kept in a register). Load them from the stack.
     fn load_value_to_reg(&mut self, value: Value, reg: &str, tmp_reg: &str) -> io::Result<()> {
         if value.is_global() {
             let global_name = self.gen.get_global_value_name(value);
@@ -417,22 +1029,25 @@ impl<'a, 'b, W: Write> FunctionGenerator<'a, 'b, W> {
                 }
             }
             ValueKind::Alloc(_) => {
-                let offset = self.stack_frame.get_stack_offset(value);
+                let (base, offset) = self.frame_slot(self.stack_frame.get_stack_offset(value));
 
-                if offset >= -2048 && offset <= 2047 {
+                if (-MAX_IMM_12 - 1..=MAX_IMM_12).contains(&offset) {
                     self.gen
                         .writer
-                        .write_inst("addi", &[reg, "sp", &offset.to_string()])
+                        .write_inst("addi", &[reg, base, &offset.to_string()])
                 } else {
                     self.gen
                         .writer
                         .write_inst("li", &[reg, &offset.to_string()])?;
-                    self.gen.writer.write_inst("add", &[reg, "sp", reg])
+                    self.gen.writer.write_inst("add", &[reg, base, reg])
                 }
             }
             // Result of other instructions
             // They should have been already stored on the stack
             _ => {
+                if let Some(&held) = self.registers.get(&value) {
+                    return self.write_move(reg, held);
+                }
                 let offset = self.stack_frame.get_stack_offset(value);
                 let addr: String = self.build_stk_addr_str(offset, tmp_reg)?;
                 self.gen.writer.write_inst("lw", &[reg, &addr])
@@ -442,18 +1057,36 @@ impl<'a, 'b, W: Write> FunctionGenerator<'a, 'b, W> {
 
     /// Save a value (global or local) from a register.
     /// If the value is global, load its address using `la` and store.
-    /// If the value is local, store it to the stack frame.
+    /// If the value is local, store it to the stack frame, or move it to
+    /// its register if it has one.
     fn save_value_from_reg(&mut self, value: Value, reg: &str, tmp_reg: &str) -> io::Result<()> {
         if value.is_global() {
             let global_name = self.gen.get_global_value_name(value);
             self.gen.writer.write_inst("la", &[tmp_reg, &global_name])?;
             return self.gen.writer.write_inst("sw", &[reg, &format!("0({})", tmp_reg)]);
         }
+        if let Some(&held) = self.registers.get(&value) {
+            return self.write_move(held, reg);
+        }
         let offset = self.stack_frame.get_stack_offset(value);
         let addr: String = self.build_stk_addr_str(offset, tmp_reg)?;
         self.gen.writer.write_inst("sw", &[reg, &addr])
     }
 
+    /// The register an instruction should compute `value` into: its own
+    /// register if it has one, `t0` otherwise
+    fn result_reg(&self, value: Value) -> String {
+        self.registers.get(&value).unwrap_or(&"t0").to_string()
+    }
+
+    /// `mv dest, src`, left out if both are the same register
+    fn write_move(&mut self, dest: &str, src: &str) -> io::Result<()> {
+        if dest == src {
+            return Ok(());
+        }
+        self.gen.writer.write_inst("mv", &[dest, src])
+    }
+
     fn get_value_kind(&self, value: Value) -> ValueKind {
         if value.is_global() {
             self.gen.get_global_value_kind(value)
@@ -471,13 +1104,7 @@ impl<'a, 'b, W: Write> FunctionGenerator<'a, 'b, W> {
     }
 
     fn get_bb_name(&self, bb: BasicBlock) -> String {
-        self.func
-            .dfg()
-            .bb(bb)
-            .name()
-            .as_ref()
-            .unwrap()
-            .replace("%", "")
+        mangle_label(self.func.dfg().bb(bb).name().as_ref().unwrap())
     }
 }
 
@@ -500,3 +1127,27 @@ fn map_binary_op(op: KoopaBinaryOp) -> Option<&'static str> {
         KoopaBinaryOp::Eq | KoopaBinaryOp::NotEq | KoopaBinaryOp::Ge | KoopaBinaryOp::Le => None,
     }
 }
+
+/// Describes the registers written by the code of `func`, split by who
+/// preserves them under the calling convention. Callee-saved registers are
+/// restored before returning. A function that makes calls may also lose
+/// any caller-saved register to its callees
+fn clobber_report(func: &FunctionData, written_regs: &BTreeSet<String>) -> String {
+    let is_callee_saved = |reg: &&String| reg.as_str() == "sp" || reg.starts_with('s');
+    let (callee_saved, caller_saved): (Vec<&String>, Vec<&String>) =
+        written_regs.iter().partition(is_callee_saved);
+    let join = |regs: Vec<&String>| {
+        regs.iter()
+            .map(|reg| reg.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let makes_calls = written_regs.contains("ra");
+    format!(
+        "clobbers {}: caller-saved [{}], callee-saved [{}]{}",
+        mangle_global(func.name()),
+        join(caller_saved),
+        join(callee_saved),
+        if makes_calls { ", makes calls" } else { "" }
+    )
+}
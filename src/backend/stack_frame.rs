@@ -2,12 +2,20 @@ use koopa::ir::entities::*;
 use koopa::ir::types::TypeKind;
 use std::collections::HashMap;
 
+use crate::backend::mangle::mangle_global;
 use crate::backend::riscv_generator::WORD_SIZE;
 
+/// Frames larger than this (1 MiB) are reported unless another limit is set
+pub const DEFAULT_MAX_FRAME_SIZE: i32 = 1 << 20;
+
 pub struct StackFrame {
     values_map: HashMap<Value, i32>, // Map Koopa IR Values to their stack offsets
     stack_size: i32,                 // Total size of the stack frame
+    raw_stack_size: i32,             // Total size before rounding up to 16 bytes
     ra_offset: Option<i32>,          // Offset for the return address if saved
+    fp_offset: Option<i32>,          // Offset for the caller's fp with `--frame-pointer`
+    callee_saved: Vec<(&'static str, i32)>, // Callee-saved registers in use, with their offsets
+    locals_offset: i32,              // Offset of the first local, above outgoing arguments
 }
 
 impl StackFrame {
@@ -15,7 +23,11 @@ impl StackFrame {
         Self {
             values_map: HashMap::new(),
             stack_size: 0,
+            raw_stack_size: 0,
             ra_offset: None,
+            fp_offset: None,
+            callee_saved: Vec::new(),
+            locals_offset: 0,
         }
     }
 
@@ -25,11 +37,20 @@ impl StackFrame {
     ///
     /// Stack frame for previous function
     /// Saved ra
+    /// Saved fp (only with `frame_pointer`)
+    /// Saved s1-s11 (those holding values in `registers`)
     /// Local variables...
     /// 10th argument
     /// 9th argument
     /// Stack frame for Next function
-    pub fn initialize(&mut self, func: &FunctionData) {
+    /// Results kept in `registers` get no slot, but the callee-saved
+    /// registers among them are saved in the frame.
+    pub fn initialize(
+        &mut self,
+        func: &FunctionData,
+        frame_pointer: bool,
+        registers: &HashMap<Value, &'static str>,
+    ) {
         self.values_map.clear();
 
         let mut has_call = false;
@@ -44,6 +65,15 @@ impl StackFrame {
             }
         }
         let ra_size = if has_call { WORD_SIZE } else { 0 }; // 4 bytes
+        let fp_size = if frame_pointer { WORD_SIZE } else { 0 };
+        let mut callee_saved: Vec<&'static str> = registers
+            .values()
+            .copied()
+            .filter(|reg| reg.starts_with('s'))
+            .collect();
+        callee_saved.sort_unstable_by_key(|reg| reg[1..].parse::<u32>().unwrap());
+        callee_saved.dedup();
+        let callee_saved_size = callee_saved.len() as i32 * WORD_SIZE;
         let call_args_size = if max_call_args > 8 {
             (max_call_args - 8) as i32 * WORD_SIZE
         } else {
@@ -56,7 +86,7 @@ impl StackFrame {
                 let inst_data = func.dfg().value(inst);
                 let ty = inst_data.ty();
 
-                if ty.is_unit() {
+                if ty.is_unit() || registers.contains_key(&inst) {
                     continue;
                 }
 
@@ -73,7 +103,9 @@ impl StackFrame {
             }
         }
 
-        let total_size = ra_size + local_size + call_args_size;
+        self.locals_offset = call_args_size;
+        let total_size = ra_size + fp_size + callee_saved_size + local_size + call_args_size;
+        self.raw_stack_size = total_size;
         self.stack_size = (total_size + 15) & !15; // Align to 16 bytes
 
         self.ra_offset = if has_call {
@@ -81,6 +113,17 @@ impl StackFrame {
         } else {
             None
         };
+        self.fp_offset = if frame_pointer {
+            Some(self.stack_size - ra_size - fp_size)
+        } else {
+            None
+        };
+        let callee_saved_top = self.stack_size - ra_size - fp_size;
+        self.callee_saved = callee_saved
+            .into_iter()
+            .enumerate()
+            .map(|(i, reg)| (reg, callee_saved_top - (i as i32 + 1) * WORD_SIZE))
+            .collect();
     }
 
     pub fn get_stack_offset(&self, value: Value) -> i32 {
@@ -97,4 +140,109 @@ impl StackFrame {
     pub fn get_ra_offset(&self) -> Option<i32> {
         self.ra_offset
     }
+
+    pub fn get_fp_offset(&self) -> Option<i32> {
+        self.fp_offset
+    }
+
+    /// Callee-saved registers the function uses, with the offsets of the
+    /// slots their caller's values are saved in
+    pub fn get_callee_saved(&self) -> &[(&'static str, i32)] {
+        &self.callee_saved
+    }
+
+    pub fn get_locals_offset(&self) -> i32 {
+        self.locals_offset
+    }
+
+    /// Checks that the frame of `func` is at most `max_size` bytes.
+    /// Such huge frames almost always come from an oversized local array
+    /// and would overflow the stack at runtime
+    pub fn check_size(&self, func: &FunctionData, max_size: i32) -> Result<(), String> {
+        if self.stack_size <= max_size {
+            return Ok(());
+        }
+        Err(format!(
+            "stack frame of function '{}' is {} bytes, exceeding the limit of {} bytes",
+            mangle_global(func.name()),
+            self.stack_size,
+            max_size
+        ))
+    }
+
+    /// Renders the frame layout of `func` for debugging.
+    /// If `rounded` is false, the raw frame size is shown instead of the
+    /// 16-byte aligned one, which makes the slot sizing easier to verify.
+    pub fn dump(&self, func: &FunctionData, rounded: bool) -> String {
+        let size = if rounded {
+            self.stack_size
+        } else {
+            self.raw_stack_size
+        };
+        let mut out = format!("frame {}: size {}", func.name(), size);
+        if let Some(ra_offset) = self.ra_offset {
+            out += &format!(", ra at {}", ra_offset);
+        }
+        if let Some(fp_offset) = self.fp_offset {
+            out += &format!(", fp at {}", fp_offset);
+        }
+        for (reg, offset) in &self.callee_saved {
+            out += &format!(", {} at {}", reg, offset);
+        }
+        out += "\n";
+
+        let mut slots: Vec<_> = self.values_map.iter().collect();
+        slots.sort_by_key(|&(_, &offset)| offset);
+        for (&value, &offset) in slots {
+            let data = func.dfg().value(value);
+            let name = match data.name() {
+                Some(name) => name.clone(),
+                None => format!("<{}>", value_kind_name(data.kind())),
+            };
+            out += &format!("  {:>6}  {}\n", offset, name);
+        }
+        out
+    }
+}
+
+/// Short mnemonic of an instruction kind, used for unnamed values in dumps
+fn value_kind_name(kind: &ValueKind) -> &'static str {
+    match kind {
+        ValueKind::Alloc(_) => "alloc",
+        ValueKind::Load(_) => "load",
+        ValueKind::GetPtr(_) => "getptr",
+        ValueKind::GetElemPtr(_) => "getelemptr",
+        ValueKind::Binary(_) => "binary",
+        ValueKind::Call(_) => "call",
+        _ => "value",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::{self, FrontendOptions};
+
+    fn frame_dumps(src: &str) -> (String, String) {
+        let ast = frontend::parse(src).unwrap();
+        let program = frontend::translate_to_koopa(ast, &FrontendOptions::default()).unwrap();
+        let &main = program.func_layout().last().unwrap();
+        let func = program.func(main);
+        let mut frame = StackFrame::new();
+        frame.initialize(func, false, &HashMap::new());
+        (frame.dump(func, true), frame.dump(func, false))
+    }
+
+    #[test]
+    fn unrounded_frame_size() {
+        // A 20-byte array and nothing else
+        let (rounded, raw) = frame_dumps("int main() { int a[5]; return 0; }");
+        assert!(rounded.starts_with("frame @main: size 32\n"), "{}", rounded);
+        assert!(raw.starts_with("frame @main: size 20\n"), "{}", raw);
+
+        // Already a multiple of 16: the two sizes agree
+        let (rounded, raw) = frame_dumps("int main() { int a[4]; return 0; }");
+        assert!(rounded.starts_with("frame @main: size 16\n"), "{}", rounded);
+        assert_eq!(rounded, raw);
+    }
 }
@@ -0,0 +1,70 @@
+use koopa::ir::{BasicBlock, FunctionData, Program, ValueKind};
+use std::io::{self, Write};
+
+/// Writes the control-flow graph of every function of `program` as a
+/// Graphviz DOT graph: one cluster per function, one node per basic block
+/// and one edge per `br`/`jump` target (`br` edges are labeled `true` and
+/// `false`).
+/// Blocks that do not end with `br`, `jump` or `ret` are drawn in red
+pub fn emit_dot(program: &Program, mut writer: impl Write) -> io::Result<()> {
+    writeln!(writer, "digraph cfg {{")?;
+    writeln!(writer, "  node [shape=box];")?;
+    for &func in program.func_layout() {
+        let func = program.func(func);
+        // Library declarations have no body
+        if func.layout().entry_bb().is_none() {
+            continue;
+        }
+        emit_function(func, &mut writer)?;
+    }
+    writeln!(writer, "}}")
+}
+
+fn emit_function(func: &FunctionData, writer: &mut impl Write) -> io::Result<()> {
+    let func_name = func.name().trim_start_matches('@');
+    writeln!(writer, "  subgraph \"cluster_{}\" {{", func_name)?;
+    writeln!(writer, "    label = \"{}\";", func.name())?;
+    let node_id = |bb: BasicBlock| format!("\"{}:{}\"", func_name, bb_name(func, bb));
+
+    for (&bb, node) in func.layout().bbs() {
+        let last = node.insts().back_key().map(|&inst| func.dfg().value(inst).kind());
+        let (edges, terminated) = match last {
+            Some(ValueKind::Branch(branch)) => (
+                vec![
+                    (branch.true_bb(), " [label=\"true\"]"),
+                    (branch.false_bb(), " [label=\"false\"]"),
+                ],
+                true,
+            ),
+            Some(ValueKind::Jump(jump)) => (vec![(jump.target(), "")], true),
+            Some(ValueKind::Return(_)) => (Vec::new(), true),
+            _ => (Vec::new(), false),
+        };
+        if terminated {
+            writeln!(writer, "    {} [label=\"{}\"];", node_id(bb), bb_name(func, bb))?;
+        } else {
+            writeln!(
+                writer,
+                "    {} [label=\"{}\\n(unterminated)\", color=red];",
+                node_id(bb),
+                bb_name(func, bb)
+            )?;
+        }
+        for (target, attributes) in edges {
+            writeln!(writer, "    {} -> {}{};", node_id(bb), node_id(target), attributes)?;
+        }
+    }
+    writeln!(writer, "  }}")
+}
+
+/// The name of `bb` in the Koopa IR, e.g. `%entry_0`, or its position in
+/// the layout if it has none
+pub(crate) fn bb_name(func: &FunctionData, bb: BasicBlock) -> String {
+    match func.dfg().bb(bb).name() {
+        Some(name) => name.clone(),
+        None => {
+            let index = func.layout().bbs().keys().take_while(|&&other| other != bb).count();
+            format!("%bb{}", index)
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use crate::ast::*;
+use crate::frontend::error::CompileError;
 use crate::frontend::koopa_context::KoopaContext;
 use koopa::ir::{builder_traits::*, *};
 
@@ -17,6 +18,7 @@ pub struct ArrayInitHelper<'init, 'ctx> {
     ctx: &'init mut KoopaContext<'ctx>,
     shape: &'init [usize], // Array dimensions [2, 3, 4]
     flat_size: usize,      // Total number of elements 24
+    constant: bool,        // Whether the elements must be constant expressions
 }
 
 impl<'init, 'ctx> ArrayInitHelper<'init, 'ctx> {
@@ -26,24 +28,36 @@ impl<'init, 'ctx> ArrayInitHelper<'init, 'ctx> {
             ctx,
             shape,
             flat_size,
+            constant: false,
         }
     }
 
+    /// Evaluates every element at compile time, as required for const
+    /// arrays. Global arrays are always initialized this way
+    pub fn constant(mut self) -> Self {
+        self.constant = true;
+        self
+    }
+
     pub fn flatten_init_list(&mut self, init: &Option<InitList>) -> Vec<Value> {
         let zero_val = self.ctx.new_integer_value(0);
         let mut result = vec![zero_val; self.flat_size];
         let mut cursor = 0;
         if let Some(init_list) = init {
-            self.flatten_recursive(init_list, 0, &mut cursor, &mut result);
+            // The outermost list initializes the whole array
+            self.flatten_recursive(init_list, 1, &mut cursor, &mut result);
         }
         result
     }
 
     /// Recursively flatten the InitList into a flat vector of Values
-    /// current_dim: current dimension in shape:
-    ///   - 0 means outside the array
-    ///   - 1 means first dimension
+    /// current_dim: the dimension in shape that `current_init` initializes,
+    /// i.e. it covers the sub-array of shape[current_dim - 1..]:
+    ///   - 1 means the whole array
+    ///   - 2 means one element of the first dimension
     ///   - ...
+    ///
+    /// Elements not covered by an initializer keep their zero value.
     fn flatten_recursive(
         &mut self,
         current_init: &InitList,
@@ -56,9 +70,9 @@ impl<'init, 'ctx> ArrayInitHelper<'init, 'ctx> {
                 if *cursor >= result.len() {
                     return;
                 }
-                let val = if self.ctx.symbol_table.is_global_scope() {
-                    let int_val = expr.compute_constexpr(self.ctx);
-                    self.ctx.new_global_value().integer(int_val)
+                let val = if self.constant || self.ctx.symbol_table.is_global_scope() {
+                    let int_val = expr.compute_constexpr_or_report(self.ctx);
+                    self.ctx.new_integer_value(int_val)
                 } else {
                     expr.generate(self.ctx)
                 };
@@ -72,26 +86,27 @@ impl<'init, 'ctx> ArrayInitHelper<'init, 'ctx> {
                 for item in list {
                     match item {
                         InitList::List(_) => {
-                            let mut next_dim = current_dim + 1;
+                            // A nested list initializes the largest proper
+                            // sub-array the cursor is aligned to
                             // int[2][3][4]
-                            // next_dim = 1 -> capacity = 2 * 3 * 4 = 24
                             // next_dim = 2 -> capacity = 3 * 4 = 12
                             // next_dim = 3 -> capacity = 4
-                            // next_dim = 4 -> panic
+                            // next_dim = 4 -> error
+                            let mut next_dim = current_dim + 1;
                             loop {
+                                if next_dim > self.shape.len() {
+                                    self.ctx.report_error(CompileError::MisalignedInitializer {
+                                        index: *cursor,
+                                        shape: self.shape.to_vec(),
+                                    });
+                                    return;
+                                }
                                 let next_capacity: usize =
                                     self.shape.iter().skip(next_dim - 1).product();
-                                if *cursor % next_capacity == 0 {
+                                if cursor.is_multiple_of(next_capacity) {
                                     break;
                                 }
                                 next_dim += 1;
-                                if next_dim > self.shape.len() {
-                                    println!("cursor: {}, shape: {:?}", cursor, self.shape);
-
-                                    panic!(
-                                        "ArrayInitHelper: cannot align cursor for nested init list"
-                                    );
-                                }
                             }
 
                             self.flatten_recursive(item, next_dim, cursor, result)
@@ -102,9 +117,7 @@ impl<'init, 'ctx> ArrayInitHelper<'init, 'ctx> {
                     }
                 }
 
-                if current_dim == 0 {
-                    return;
-                }
+                // Skip the zero-filled rest of this sub-array
                 let capacity: usize = self.shape.iter().skip(current_dim - 1).product();
 
                 let end_cursor = start_cursor + capacity;
@@ -142,22 +155,38 @@ impl<'init, 'ctx> ArrayInitHelper<'init, 'ctx> {
     }
 
     /// Generate store instructions for local array initialization
+    /// Multi-dimensional arrays are addressed through their flat index:
+    /// a pointer to the first element is computed once, then each element
+    /// takes a single `getptr` instead of one `getelemptr` per dimension
     pub fn generate_local_init(&mut self, alloc_ptr: Value, flat_values: &[Value]) {
-        for (i, &val) in flat_values.iter().enumerate() {
-            let mut idx = i;
-            let mut ptr = alloc_ptr;
-
-            for (dim_idx, &_dim_size) in self.shape.iter().enumerate() {
-                let stride: usize = self.shape.iter().skip(dim_idx + 1).product();
-                let current_idx = idx / stride;
-                idx %= stride;
-
-                let idx_val = self.ctx.new_value().integer(current_idx as i32);
-
-                ptr = self.ctx.new_value().get_elem_ptr(ptr, idx_val);
+        if self.shape.len() == 1 {
+            for (i, &val) in flat_values.iter().enumerate() {
+                let idx_val = self.ctx.new_value().integer(i as i32);
+                let ptr = self.ctx.new_value().get_elem_ptr(alloc_ptr, idx_val);
                 self.ctx.add_inst(ptr);
+                let store = self.ctx.new_value().store(val, ptr);
+                self.ctx.add_inst(store);
             }
+            return;
+        }
+
+        // [[i32, 3], 2] -> *i32 pointing at element [0][0]
+        let mut first_elem = alloc_ptr;
+        for _ in self.shape {
+            let zero = self.ctx.new_value().integer(0);
+            first_elem = self.ctx.new_value().get_elem_ptr(first_elem, zero);
+            self.ctx.add_inst(first_elem);
+        }
 
+        for (i, &val) in flat_values.iter().enumerate() {
+            let ptr = if i == 0 {
+                first_elem
+            } else {
+                let idx_val = self.ctx.new_value().integer(i as i32);
+                let ptr = self.ctx.new_value().get_ptr(first_elem, idx_val);
+                self.ctx.add_inst(ptr);
+                ptr
+            };
             let store = self.ctx.new_value().store(val, ptr);
             self.ctx.add_inst(store);
         }
@@ -0,0 +1,184 @@
+use std::fmt;
+
+/// A syntax error, or a semantic error found while generating Koopa IR.
+/// Generation goes on after reporting a semantic error, so that several of
+/// them can be reported at once
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompileError {
+    /// Source text that does not parse, at a 1-based line and column
+    Syntax {
+        line: usize,
+        col: usize,
+        message: String,
+    },
+    /// An identifier that is not declared in any enclosing scope
+    UndeclaredIdentifier(String),
+    /// A variable or constant declared twice in the same scope
+    Redefinition(String),
+    /// A symbol declared again as a function, or a function declared again
+    /// as a variable
+    KindMismatch(String),
+    /// A function declared again with a different signature
+    ConflictingDeclaration(String),
+    /// A function defined twice
+    FunctionRedefinition(String),
+    /// A library function redefined after the program has called it
+    DefinedAfterCall(String),
+    /// A label defined twice in the same function
+    LabelRedefinition(String),
+    /// A `goto` to a label the function does not define
+    UndeclaredLabel(String),
+    /// `main` declared with parameters (their number)
+    MainParameters(usize),
+    /// `main` declared as `void`
+    MainReturnType,
+    /// `return <expr>;` in a `void` function
+    ReturnValueFromVoid(String),
+    /// `return;` in an `int` function
+    MissingReturnValue(String),
+    /// An assignment to a constant
+    AssignToConst(String),
+    /// An assignment to an element of a const array
+    AssignToConstArray(String),
+    /// An assignment to a function name
+    AssignToFunction(String),
+    /// `break` outside of a loop
+    BreakOutsideLoop,
+    /// `continue` outside of a loop
+    ContinueOutsideLoop,
+    /// A function name used as a value
+    FunctionAsValue(String),
+    /// The result of a call to a `void` function used as a value
+    VoidCallAsValue(String),
+    /// A call to something that is not a function
+    NotAFunction(String),
+    /// A call with the wrong number of arguments
+    ArgumentCount {
+        function: String,
+        expected: usize,
+        found: usize,
+    },
+    /// An expression that must be constant but is not, and why
+    NotConstant(String),
+    /// The initializer of a constant or global variable is not constant
+    InvalidInitializer { name: String, reason: String },
+    /// An array dimension (counting from 1) that is not constant
+    ArraySizeNotConstant {
+        name: String,
+        dimension: usize,
+        reason: String,
+    },
+    /// An array dimension (counting from 1) that is zero or negative
+    NonPositiveArraySize {
+        name: String,
+        dimension: usize,
+        size: i32,
+    },
+    /// A nested initializer list that does not start at a sub-array
+    /// boundary, with the index of the element it starts at
+    MisalignedInitializer { index: usize, shape: Vec<usize> },
+    /// The program is valid but cannot be emitted, e.g. it uses a feature
+    /// the target does not support
+    Backend(String),
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::Syntax { line, col, message } => {
+                write!(f, "{}:{}: {}", line, col, message)
+            }
+            CompileError::UndeclaredIdentifier(name) => {
+                write!(f, "use of undeclared identifier '{}'", name)
+            }
+            CompileError::Redefinition(name) => write!(f, "redefinition of '{}'", name),
+            CompileError::KindMismatch(name) => {
+                write!(f, "'{}' redeclared as a different kind of symbol", name)
+            }
+            CompileError::ConflictingDeclaration(name) => {
+                write!(f, "conflicting declaration of '{}'", name)
+            }
+            CompileError::FunctionRedefinition(name) => {
+                write!(f, "redefinition of function '{}'", name)
+            }
+            CompileError::DefinedAfterCall(name) => {
+                write!(f, "'{}' is defined after it has been called", name)
+            }
+            CompileError::LabelRedefinition(name) => write!(f, "redefinition of label '{}'", name),
+            CompileError::UndeclaredLabel(name) => write!(f, "use of undeclared label '{}'", name),
+            CompileError::MainParameters(count) => write!(
+                f,
+                "'main' must not take parameters, found {} (SysY only supports `int main()`)",
+                count
+            ),
+            CompileError::MainReturnType => write!(f, "'main' must return 'int'"),
+            CompileError::ReturnValueFromVoid(name) => {
+                write!(f, "returning a value from void function '{}'", name)
+            }
+            CompileError::MissingReturnValue(name) => {
+                write!(f, "non-void function '{}' must return a value", name)
+            }
+            CompileError::AssignToConst(name) => {
+                write!(f, "cannot assign to constant variable '{}'", name)
+            }
+            CompileError::AssignToConstArray(name) => {
+                write!(f, "cannot assign to element of const array '{}'", name)
+            }
+            CompileError::AssignToFunction(name) => {
+                write!(f, "cannot assign to function '{}'", name)
+            }
+            CompileError::BreakOutsideLoop => write!(f, "'break' statement not in loop"),
+            CompileError::ContinueOutsideLoop => write!(f, "'continue' statement not in loop"),
+            CompileError::FunctionAsValue(name) => {
+                write!(f, "function '{}' cannot be used as a value", name)
+            }
+            CompileError::VoidCallAsValue(name) => {
+                write!(
+                    f,
+                    "call to void function '{}' cannot be used as a value",
+                    name
+                )
+            }
+            CompileError::NotAFunction(name) => write!(f, "'{}' is not a function", name),
+            CompileError::ArgumentCount {
+                function,
+                expected,
+                found,
+            } => write!(
+                f,
+                "function '{}' expects {} argument(s), found {}",
+                function, expected, found
+            ),
+            CompileError::NotConstant(reason) => write!(f, "{}", reason),
+            CompileError::InvalidInitializer { name, reason } => {
+                write!(f, "in initializer of '{}': {}", name, reason)
+            }
+            CompileError::ArraySizeNotConstant {
+                name,
+                dimension,
+                reason,
+            } => write!(
+                f,
+                "array size must be a constant expression (dimension {} of '{}': {})",
+                dimension, name, reason
+            ),
+            CompileError::NonPositiveArraySize {
+                name,
+                dimension,
+                size,
+            } => write!(
+                f,
+                "array size must be positive (dimension {} of '{}' is {})",
+                dimension, name, size
+            ),
+            CompileError::MisalignedInitializer { index, shape } => write!(
+                f,
+                "misaligned nested initializer list at element {} of array of shape {:?}",
+                index, shape
+            ),
+            CompileError::Backend(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
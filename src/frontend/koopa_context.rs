@@ -2,7 +2,22 @@ use koopa::ir::builder::{BasicBlockBuilder, GlobalBuilder, LocalBuilder};
 use koopa::ir::entities::ValueKind;
 use koopa::ir::{builder_traits::*, *};
 
+use crate::frontend::error::CompileError;
 use crate::frontend::symbol_table::*;
+use crate::frontend::FrontendOptions;
+use std::collections::HashMap;
+
+/// A `goto` target in the current function
+struct Label {
+    bb: BasicBlock,
+    defined: bool,
+}
+
+/// Compile-time contents of a const array
+struct ConstArrayValues {
+    shape: Vec<usize>,
+    values: Vec<i32>, // Flattened in row-major order
+}
 
 /// Context for Koopa IR generation
 pub struct KoopaContext<'a> {
@@ -16,10 +31,18 @@ pub struct KoopaContext<'a> {
     // For while loops, they should always be operated in pairs
     loop_break_stack: Vec<BasicBlock>,
     loop_continue_stack: Vec<BasicBlock>,
+    // Labels of the current function. A label's basic block is created by
+    // whichever comes first, the label or a `goto` to it, and is added to
+    // the layout once the label is reached
+    labels: HashMap<String, Label>,
+    // Element values of const arrays, keyed by their alloc
+    const_arrays: HashMap<Value, ConstArrayValues>,
+    options: FrontendOptions,
+    errors: Vec<CompileError>, // Errors reported so far
 }
 
 impl<'a> KoopaContext<'a> {
-    pub fn new(program: &'a mut Program) -> Self {
+    pub fn new(program: &'a mut Program, options: FrontendOptions) -> Self {
         KoopaContext {
             program,
             current_func: None,
@@ -28,7 +51,67 @@ impl<'a> KoopaContext<'a> {
             bb_count: 0,
             loop_break_stack: Vec::new(),
             loop_continue_stack: Vec::new(),
+            labels: HashMap::new(),
+            const_arrays: HashMap::new(),
+            options,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Records an error and lets generation go on.
+    /// Errors beyond the `max_errors` limit are dropped.
+    pub fn report_error(&mut self, error: CompileError) {
+        if !self.error_limit_reached() {
+            self.errors.push(error);
+        }
+    }
+
+    /// Warns about code that can never execute (a statement following a
+    /// return, break, continue or goto in the same block), if enabled
+    /// with `-Wunreachable`
+    pub fn report_unreachable(&mut self, what: &str) {
+        if self.options.warn_unreachable {
+            let func_name = self.current_func().name().trim_start_matches('@');
+            eprintln!(
+                "warning: unreachable code in function '{}': {} is never executed",
+                func_name, what
+            );
+        }
+    }
+
+    /// Whether `sizeof(...)` is the size extension rather than a call
+    /// (see `FrontendOptions::sizeof`)
+    pub fn is_sizeof(&self, func_name: &str) -> bool {
+        func_name == "sizeof"
+            && self.options.sizeof
+            && self.symbol_table.lookup(func_name).is_none()
+    }
+
+    /// Whether enough errors have been collected to stop generating
+    pub fn error_limit_reached(&self) -> bool {
+        self.options.max_errors != 0 && self.errors.len() >= self.options.max_errors
+    }
+
+    pub fn take_errors(&mut self) -> Vec<CompileError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Looks up `name`, reporting an error if it is not declared
+    pub fn lookup_symbol(&mut self, name: &str) -> Option<SymbolInfo> {
+        let symbol = self.symbol_table.lookup(name);
+        if symbol.is_none() {
+            self.report_error(CompileError::UndeclaredIdentifier(name.to_string()));
         }
+        symbol
+    }
+
+    /// Inserts a variable into the current scope, reporting an error if
+    /// the scope already declares `name`
+    pub fn declare_symbol(&mut self, name: &str, info: SymbolInfo) {
+        if self.symbol_table.contains_local(name) {
+            self.report_error(CompileError::Redefinition(name.to_string()));
+        }
+        self.symbol_table.insert(name.to_string(), info);
     }
 
     pub fn current_func_mut(&mut self) -> &mut FunctionData {
@@ -45,6 +128,14 @@ impl<'a> KoopaContext<'a> {
         )
     }
 
+    /// Return type of the current function (unit for `void` functions)
+    pub fn current_func_return_type(&self) -> Type {
+        let TypeKind::Function(_, ret) = self.current_func().ty().kind() else {
+            unreachable!("Function must have a function type");
+        };
+        ret.clone()
+    }
+
     pub fn set_current_func(&mut self, func: Function) {
         self.current_func = Some(func);
         self.current_bb = None;
@@ -115,20 +206,103 @@ impl<'a> KoopaContext<'a> {
         self.loop_continue_stack.pop();
     }
 
-    /// Clones and returns the current loop's continue target basic block
-    pub fn get_current_loop_break_target(&self) -> BasicBlock {
-        self.loop_break_stack
-            .last()
-            .expect("No current loop break target found")
-            .clone()
+    /// Returns the current loop's break target basic block, or None
+    /// outside of any loop
+    pub fn get_current_loop_break_target(&self) -> Option<BasicBlock> {
+        self.loop_break_stack.last().copied()
+    }
+
+    /// Returns the current loop's continue target basic block, or None
+    /// outside of any loop
+    pub fn get_current_loop_continue_target(&self) -> Option<BasicBlock> {
+        self.loop_continue_stack.last().copied()
+    }
+
+    /// Returns the basic block of label `name`, creating it if the label
+    /// has not been seen yet (a forward `goto`)
+    pub fn label_bb(&mut self, name: &str) -> BasicBlock {
+        if let Some(label) = self.labels.get(name) {
+            return label.bb;
+        }
+        let bb = self.new_bb(&format!("%{}", name));
+        self.labels.insert(name.to_string(), Label { bb, defined: false });
+        bb
     }
 
-    /// Clones and returns the current loop's continue target basic block
-    pub fn get_current_loop_continue_target(&self) -> BasicBlock {
-        self.loop_continue_stack
-            .last()
-            .expect("No current loop continue target found")
-            .clone()
+    /// Marks label `name` as defined and returns its basic block.
+    /// Reports an error and returns None if it is already defined.
+    pub fn define_label(&mut self, name: &str) -> Option<BasicBlock> {
+        let bb = self.label_bb(name);
+        let label = self.labels.get_mut(name).unwrap();
+        if label.defined {
+            self.report_error(CompileError::LabelRedefinition(name.to_string()));
+            return None;
+        }
+        label.defined = true;
+        Some(bb)
+    }
+
+    /// Reports labels of the current function that are used by a `goto`
+    /// but never defined, then forgets all of its labels
+    pub fn finish_labels(&mut self) {
+        let mut undefined: Vec<String> = self
+            .labels
+            .drain()
+            .filter(|(_, label)| !label.defined)
+            .map(|(name, _)| name)
+            .collect();
+        undefined.sort();
+        for name in undefined {
+            self.report_error(CompileError::UndeclaredLabel(name));
+        }
+    }
+
+    /// Records the initial values of the const array allocated by `alloc`.
+    /// `flat_values` must all be integers
+    pub fn set_const_array_values(&mut self, alloc: Value, shape: &[usize], flat_values: &[Value]) {
+        let values = flat_values
+            .iter()
+            .map(|&value| match self.get_value_kind(value) {
+                ValueKind::Integer(n) => n.value(),
+                _ => unreachable!("Const array elements are evaluated at compile time"),
+            })
+            .collect();
+        let shape = shape.to_vec();
+        self.const_arrays
+            .insert(alloc, ConstArrayValues { shape, values });
+    }
+
+    /// Reads element `indices` of the const array `name` allocated by `alloc`.
+    /// Only a full index path yields a constant (`M[1]` is an array)
+    pub fn const_array_element(
+        &self,
+        alloc: Value,
+        name: &str,
+        indices: &[i32],
+    ) -> Result<i32, String> {
+        let array = &self.const_arrays[&alloc];
+        if indices.len() != array.shape.len() {
+            return Err(format!(
+                "'{}' has {} dimension(s) but is indexed with {} in a constant expression",
+                name,
+                array.shape.len(),
+                indices.len()
+            ));
+        }
+        let mut offset = 0;
+        for (i, (&index, &dim)) in indices.iter().zip(&array.shape).enumerate() {
+            if index < 0 || index as usize >= dim {
+                return Err(format!(
+                    "index {} is out of bounds for dimension {} of '{}' (size {})",
+                    index,
+                    i + 1,
+                    name,
+                    dim
+                ));
+            }
+            offset = offset * dim + index as usize;
+        }
+        Ok(array.values[offset])
     }
 
     pub fn is_current_bb_terminated(&mut self) -> bool {
@@ -138,10 +312,10 @@ impl<'a> KoopaContext<'a> {
         let bb_node = func_data.layout_mut().bb_mut(current_bb);
         if let Some(&last_inst) = bb_node.insts().back_key() {
             let inst_data = func_data.dfg().value(last_inst);
-            match inst_data.kind() {
-                ValueKind::Branch(_) | ValueKind::Jump(_) | ValueKind::Return(_) => true,
-                _ => false,
-            }
+            matches!(
+                inst_data.kind(),
+                ValueKind::Branch(_) | ValueKind::Jump(_) | ValueKind::Return(_)
+            )
         } else {
             false // No instructions in the current basic block
         }
@@ -180,10 +354,97 @@ impl<'a> KoopaContext<'a> {
 
         for (name, param_types, ret_type) in sysy_lib_functions {
             let func_data = FunctionData::new_decl(format!("@{}", name), param_types, ret_type);
-            let func = self.program.new_func(func_data);
-            self.symbol_table
-                .insert(name.to_string(), SymbolInfo::Function(func));
+            self.register_function(name, func_data);
+        }
+    }
+
+    /// Adds `func_data` to the program and inserts it into the current
+    /// symbol table as `name`.
+    /// A definition may replace an earlier declaration (e.g. a SysY library
+    /// function) of the same signature as long as nothing calls it yet.
+    /// Any other clash with an existing symbol is an error.
+    /// The function is appended to the program's function layout, so
+    /// functions are emitted in the order they are defined in the source.
+    pub fn register_function(&mut self, name: &str, func_data: FunctionData) -> Function {
+        if let Some(existing) = self.symbol_table.lookup(name) {
+            if let Err(error) = self.check_redeclaration(name, existing, &func_data) {
+                self.report_error(error);
+            } else if let SymbolInfo::Function(old) = existing {
+                self.program.remove_func(old);
+            }
         }
+        let func = self.program.new_func(func_data);
+        self.symbol_table
+            .insert(name.to_string(), SymbolInfo::Function(func));
+        func
+    }
+
+    /// Declares the bodyless function `func_data` as `name`, like the
+    /// SysY library functions.
+    /// Redeclaring a function (including a library function or one that is
+    /// already defined) with the same signature is a no-op.
+    pub fn declare_function(&mut self, name: &str, func_data: FunctionData) {
+        match self.symbol_table.lookup(name) {
+            Some(SymbolInfo::Function(old)) => {
+                if self.program.func(old).ty() != func_data.ty() {
+                    self.report_error(CompileError::ConflictingDeclaration(name.to_string()));
+                }
+            }
+            Some(_) => {
+                self.report_error(CompileError::KindMismatch(name.to_string()));
+            }
+            None => {
+                self.register_function(name, func_data);
+            }
+        }
+    }
+
+    /// Checks whether `func_data` may replace the existing symbol `name`
+    fn check_redeclaration(
+        &self,
+        name: &str,
+        existing: SymbolInfo,
+        func_data: &FunctionData,
+    ) -> Result<(), CompileError> {
+        let SymbolInfo::Function(old) = existing else {
+            return Err(CompileError::KindMismatch(name.to_string()));
+        };
+        let old_data = self.program.func(old);
+        if old_data.ty() != func_data.ty() {
+            return Err(CompileError::ConflictingDeclaration(name.to_string()));
+        }
+        if old_data.layout().entry_bb().is_some() {
+            return Err(CompileError::FunctionRedefinition(name.to_string()));
+        }
+        if self.is_function_called(old) {
+            return Err(CompileError::DefinedAfterCall(name.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Removes the function declarations (e.g. unused SysY library
+    /// functions) that nothing calls, so they do not clutter the output
+    pub fn prune_unused_declarations(&mut self) {
+        let unused: Vec<Function> = self
+            .program
+            .func_layout()
+            .iter()
+            .copied()
+            .filter(|&func| self.program.func(func).layout().entry_bb().is_none())
+            .filter(|&func| !self.is_function_called(func))
+            .collect();
+        for func in unused {
+            self.program.remove_func(func);
+        }
+    }
+
+    /// Whether any instruction in the program calls `func`
+    fn is_function_called(&self, func: Function) -> bool {
+        self.program.funcs().values().any(|data| {
+            data.dfg().values().values().any(|value| {
+                matches!(value.kind(), ValueKind::Call(call) if call.callee() == func)
+            })
+        })
     }
 
     /// Pushes basic block `bb` to the end of the basic block list of
@@ -210,13 +471,61 @@ impl<'a> KoopaContext<'a> {
             .expect("Failed to add instruction");
     }
 
+    /// Pushes instruction `inst` to the front of the entry basic block of
+    /// the current function, where it runs even if the current basic
+    /// block is unreachable
+    pub fn add_entry_inst(&mut self, inst: Value) {
+        let func = self.current_func_mut();
+        let entry = func
+            .layout()
+            .entry_bb()
+            .expect("Current function has no entry basic block");
+        func.layout_mut()
+            .bb_mut(entry)
+            .insts_mut()
+            .push_key_front(inst)
+            .expect("Failed to add instruction");
+    }
+
+    /// Runs `generate` in a fresh basic block and then removes every block
+    /// and instruction it added, so the code is checked for errors but
+    /// never emitted
+    pub fn discard(&mut self, generate: impl FnOnce(&mut Self)) {
+        let saved_bb = self.current_bb;
+        let first_new = self.current_func_mut().layout().bbs().len();
+        let bb = self.new_bb("%discarded");
+        self.add_bb(bb);
+        self.set_current_bb(bb);
+        generate(self);
+        self.current_bb = saved_bb;
+
+        let func = self.current_func_mut();
+        let new_bbs: Vec<BasicBlock> = func
+            .layout()
+            .bbs()
+            .keys()
+            .skip(first_new)
+            .copied()
+            .collect();
+        // Users are removed before the values they use
+        for &bb in new_bbs.iter().rev() {
+            while let Some((inst, _)) = func.layout_mut().bb_mut(bb).insts_mut().pop_back() {
+                func.dfg_mut().remove_value(inst);
+            }
+        }
+        for bb in new_bbs {
+            func.layout_mut().bbs_mut().remove(&bb);
+            func.dfg_mut().remove_bb(bb);
+        }
+    }
+
     /// Creates a new value in the DataFlow Graph of the current function
     /// Returns a LocalBuilder for the newly created value
-    pub fn new_value(&mut self) -> LocalBuilder {
+    pub fn new_value(&mut self) -> LocalBuilder<'_> {
         self.current_func_mut().dfg_mut().new_value()
     }
 
-    pub fn new_global_value(&mut self) -> GlobalBuilder {
+    pub fn new_global_value(&mut self) -> GlobalBuilder<'_> {
         self.program.new_value()
     }
 
@@ -1,51 +1,72 @@
 use crate::ast::{BinaryOp as AstBinaryOp, *};
 use crate::frontend::{
-    array_init_helper::*, koopa_context::KoopaContext, symbol_table::SymbolInfo,
+    array_init_helper::*,
+    error::CompileError,
+    koopa_context::KoopaContext,
+    symbol_table::{SymbolInfo, SymbolTable},
 };
 use koopa::ir::{builder_traits::*, values::BinaryOp as KoopaBinaryOp, *};
 
 /// Trait for generating Koopa IR entities
 pub trait GenerateKoopa {
-    fn generate(&self, ctx: &mut KoopaContext) -> ();
+    fn generate(&self, ctx: &mut KoopaContext);
 }
 
 impl GenerateKoopa for CompUnit {
-    fn generate(&self, ctx: &mut KoopaContext) -> () {
+    fn generate(&self, ctx: &mut KoopaContext) {
         // Register all SysY library functions
         ctx.register_sysy_lib_functions();
 
+        // Function signatures are registered in a first pass, so that a
+        // function can call one defined later in the file. The global scope
+        // at each definition is kept for the second pass
+        let mut bodies = Vec::new();
         for item in &self.items {
+            if ctx.error_limit_reached() {
+                break;
+            }
             match item {
                 GlobalItem::Decl(decl) => decl.generate(ctx),
-                GlobalItem::FuncDef(func_def) => func_def.generate(ctx),
+                GlobalItem::FuncDef(func_def) => {
+                    let func = func_def.register(ctx);
+                    bodies.push((func_def, func, ctx.symbol_table.clone()));
+                }
+                GlobalItem::FuncDecl(func_decl) => func_decl.generate(ctx),
             }
         }
+
+        // Each body sees the globals declared before its function, as in a
+        // single pass, and every function
+        let functions = ctx.symbol_table.functions();
+        let global_scope = std::mem::replace(&mut ctx.symbol_table, SymbolTable::new());
+        for (func_def, func, mut scope) in bodies {
+            if ctx.error_limit_reached() {
+                break;
+            }
+            for (name, func) in &functions {
+                scope.insert(name.clone(), SymbolInfo::Function(*func));
+            }
+            ctx.symbol_table = scope;
+            func_def.generate_body(func, ctx);
+        }
+        ctx.symbol_table = global_scope;
+        ctx.prune_unused_declarations();
     }
 }
 
-impl GenerateKoopa for FuncDef {
-    fn generate(&self, ctx: &mut KoopaContext) -> () {
+impl FuncDef {
+    /// Adds the function, without its body, to the program and the global
+    /// symbol table
+    fn register(&self, ctx: &mut KoopaContext) -> Function {
+        if self.func_name == "main" {
+            self.check_main_signature(ctx);
+        }
+
         // Create and register the function
         let func_params_config: Vec<_> = self
             .params
             .iter()
-            .map(|param| {
-                let base_type = match param.base_type {
-                    DataType::Int => Type::get_i32(),
-                };
-                let ty = match param.dims {
-                    Some(ref dims) => {
-                        let shape: Vec<usize> = dims
-                            .iter()
-                            .map(|dim_expr| dim_expr.compute_constexpr(ctx) as usize)
-                            .collect();
-                        Type::get_pointer(build_array_type(base_type, &shape))
-                    }
-                    None => base_type,
-                };
-                let name = format!("@{}", param.name);
-                (Some(name), ty)
-            })
+            .map(|param| (Some(format!("@{}", param.name)), param.koopa_type(ctx)))
             .collect(); // Vector of (param_name, param_type) tuples
         let ret_type = match self.func_type {
             FuncType::Int => Type::get_i32(),
@@ -56,11 +77,13 @@ impl GenerateKoopa for FuncDef {
             func_params_config.clone(),
             ret_type,
         );
-        let func = ctx.program.new_func(func_data);
-        ctx.set_current_func(func);
         // Insert the function into global symbol table
-        ctx.symbol_table
-            .insert(self.func_name.clone(), SymbolInfo::Function(func));
+        ctx.register_function(&self.func_name, func_data)
+    }
+
+    /// Generates the body of `func`, registered by `register`
+    fn generate_body(&self, func: Function, ctx: &mut KoopaContext) {
+        ctx.set_current_func(func);
 
         // Create entry basic block
         let entry_bb: BasicBlock = ctx.new_bb("%entry");
@@ -68,23 +91,13 @@ impl GenerateKoopa for FuncDef {
         ctx.set_current_bb(entry_bb);
 
         // Set up stack arguments: alloc & store
+        // Each parameter is copied into its own slot and bound to that slot,
+        // so parameters are ordinary mutable locals: `x = 5;` stores to %x
+        // and the incoming argument (`@x`, i.e. a0-a7) is only read here
         ctx.symbol_table.enter_scope(); // Enter function scope
         for (i, arg) in self.params.iter().enumerate() {
             let value: Value = ctx.current_func().params()[i];
-
-            let base_type = match arg.base_type {
-                DataType::Int => Type::get_i32(),
-            };
-            let ty = match arg.dims {
-                Some(ref dims) => {
-                    let shape: Vec<usize> = dims
-                        .iter()
-                        .map(|dim_expr| dim_expr.compute_constexpr(ctx) as usize)
-                        .collect();
-                    Type::get_pointer(build_array_type(base_type, &shape))
-                }
-                None => base_type,
-            };
+            let ty = ctx.get_value_type(value);
             let name = format!("%{}", arg.name);
 
             let alloc_inst = ctx.new_value().alloc(ty);
@@ -94,12 +107,12 @@ impl GenerateKoopa for FuncDef {
             let store_inst = ctx.new_value().store(value, alloc_inst);
             ctx.add_inst(store_inst);
 
-            ctx.symbol_table
-                .insert(arg.name.clone(), SymbolInfo::Variable(alloc_inst));
+            ctx.declare_symbol(&arg.name, SymbolInfo::Variable(alloc_inst));
         }
 
         // Generate function body
         self.block.generate(ctx);
+        ctx.finish_labels();
 
         // Default return if no return statement is present
         if !ctx.is_current_bb_terminated() {
@@ -115,16 +128,95 @@ impl GenerateKoopa for FuncDef {
         }
         ctx.symbol_table.exit_scope(); // Exit function scope
     }
+
+    /// `main` is called by the runtime as `int main()`, so it can neither
+    /// take parameters (they would be left uninitialized) nor return void
+    fn check_main_signature(&self, ctx: &mut KoopaContext) {
+        if !self.params.is_empty() {
+            ctx.report_error(CompileError::MainParameters(self.params.len()));
+        }
+        if let FuncType::Void = self.func_type {
+            ctx.report_error(CompileError::MainReturnType);
+        }
+    }
+}
+
+impl GenerateKoopa for FuncDecl {
+    fn generate(&self, ctx: &mut KoopaContext) {
+        let param_types = self
+            .params
+            .iter()
+            .map(|param| param.koopa_type(ctx))
+            .collect();
+        let ret_type = match self.func_type {
+            FuncType::Int => Type::get_i32(),
+            FuncType::Void => Type::get_unit(),
+        };
+        let func_data =
+            FunctionData::new_decl(format!("@{}", self.func_name), param_types, ret_type);
+        ctx.declare_function(&self.func_name, func_data);
+    }
+}
+
+impl FuncFParam {
+    /// Builds the Koopa type of a function parameter.
+    /// Array parameters decay to a pointer to their element type, e.g.
+    /// `int a[][3]` has type `*[i32, 3]` and `int a[]` has type `*i32`
+    fn koopa_type(&self, ctx: &mut KoopaContext) -> Type {
+        let base_type = match self.base_type {
+            DataType::Int => Type::get_i32(),
+        };
+        match self.dims {
+            Some(ref dims) => {
+                // The first dimension of an array parameter is dropped
+                let shape = compute_array_shape(&self.name, dims, 2, ctx);
+                Type::get_pointer(build_array_type(base_type, &shape))
+            }
+            None => base_type,
+        }
+    }
 }
 
 impl GenerateKoopa for Block {
-    fn generate(&self, ctx: &mut KoopaContext) -> () {
+    fn generate(&self, ctx: &mut KoopaContext) {
+        // Whether the current run of unreachable items has been warned about
+        let mut warned = false;
         for item in &self.items {
-            if ctx.is_current_bb_terminated() {
-                // Dead code elimination: stop generating further instructions
-                // e.g., return 1; return 2; <- the second return is dead code
+            if ctx.error_limit_reached() {
                 break;
             }
+            if !ctx.is_current_bb_terminated() {
+                warned = false;
+            } else {
+                let reachable_by_goto =
+                    matches!(item, BlockItem::Stmt(stmt) if stmt.contains_label());
+                let is_empty = matches!(item, BlockItem::Stmt(Stmt::Expression { expr: None }));
+                if !warned && !reachable_by_goto && !is_empty {
+                    ctx.report_unreachable(&item.describe());
+                    warned = true;
+                }
+                // Dead code elimination: skip statements that cannot be reached
+                // e.g., return 1; return 2; <- the second return is dead code
+                // Code containing a label can still be reached by a `goto`, so
+                // it is generated into a fresh (possibly unreachable) block
+                let stmt = match item {
+                    BlockItem::Stmt(stmt) => stmt,
+                    // A later label can still use the variable, so it is
+                    // declared, but its initializer can never run
+                    BlockItem::Decl(decl) => {
+                        decl.generate_with_init(ctx, false);
+                        continue;
+                    }
+                };
+                if !stmt.contains_label() {
+                    continue;
+                }
+                if !matches!(stmt, Stmt::Label(_)) {
+                    let bb = ctx.new_bb("%unreachable");
+                    ctx.add_bb(bb);
+                    ctx.set_current_bb(bb);
+                }
+            }
             match item {
                 BlockItem::Stmt(stmt) => stmt.generate(ctx),
                 BlockItem::Decl(decl) => decl.generate(ctx),
@@ -133,30 +225,73 @@ impl GenerateKoopa for Block {
     }
 }
 
+impl BlockItem {
+    /// Short description of the item for diagnostics, e.g. "assignment to 'x'"
+    fn describe(&self) -> String {
+        let stmt = match self {
+            BlockItem::Decl(
+                Decl::Const { var_name, .. }
+                | Decl::Var { var_name, .. }
+                | Decl::Array { var_name, .. }
+                | Decl::ConstArray { var_name, .. },
+            ) => return format!("declaration of '{}'", var_name),
+            BlockItem::Stmt(stmt) => stmt,
+        };
+        match stmt {
+            Stmt::Assign {
+                lval: Expr::LVal { name, .. },
+                ..
+            } => format!("assignment to '{}'", name),
+            Stmt::Assign { .. } => "assignment".to_string(),
+            Stmt::Expression {
+                expr: Some(Expr::Call { func_name, .. }),
+            } => format!("call to '{}'", func_name),
+            Stmt::Expression { .. } => "expression statement".to_string(),
+            Stmt::Return { .. } => "return statement".to_string(),
+            Stmt::Block { .. } => "block".to_string(),
+            Stmt::If { .. } => "if statement".to_string(),
+            Stmt::While { .. } => "while loop".to_string(),
+            Stmt::DoWhile { .. } => "do-while loop".to_string(),
+            Stmt::For { .. } => "for loop".to_string(),
+            Stmt::Break => "break statement".to_string(),
+            Stmt::Continue => "continue statement".to_string(),
+            Stmt::Label(name) => format!("label '{}'", name),
+            Stmt::Goto(name) => format!("goto '{}'", name),
+        }
+    }
+}
+
 impl GenerateKoopa for Decl {
-    fn generate(&self, ctx: &mut KoopaContext) -> () {
+    fn generate(&self, ctx: &mut KoopaContext) {
+        self.generate_with_init(ctx, true);
+    }
+}
+
+impl Decl {
+    /// Declares the constant or variable. Without `initialize`, as for a
+    /// declaration skipped by a `goto`, a local is allocated in the entry
+    /// block and left uninitialized
+    fn generate_with_init(&self, ctx: &mut KoopaContext, initialize: bool) {
         match self {
             Decl::Const {
                 var_name,
                 init_list,
                 ..
             } => {
+                // Scalar constants are folded and never allocated: uses of
+                // the name (including array dimensions) become the integer,
+                // and assignments to it are rejected
                 let init_expr = unwrap_init_list(init_list);
-                let init_value: i32 = init_expr.compute_constexpr(ctx);
+                let init_value: i32 = init_expr.compute_initializer_or_report(var_name, ctx);
                 let init_handle = ctx.new_integer_value(init_value);
-                ctx.symbol_table
-                    .insert(var_name.clone(), SymbolInfo::ConstVariable(init_handle));
+                ctx.declare_symbol(var_name, SymbolInfo::ConstVariable(init_handle));
             }
             Decl::Var {
                 var_type,
                 var_name,
                 init_list,
             } => {
-                let init_expr = if let Some(init_list) = init_list {
-                    Some(unwrap_init_list(init_list))
-                } else {
-                    None
-                };
+                let init_expr = init_list.as_ref().map(unwrap_init_list);
                 let var_type = match var_type {
                     DataType::Int => Type::get_i32(),
                 };
@@ -164,7 +299,7 @@ impl GenerateKoopa for Decl {
                 if ctx.symbol_table.is_global_scope() {
                     let init = if let Some(expr) = init_expr {
                         // Initializer for global variables must be a constexpr
-                        let init_value = expr.compute_constexpr(ctx);
+                        let init_value = expr.compute_initializer_or_report(var_name, ctx);
                         ctx.new_global_value().integer(init_value)
                     } else {
                         // Default initialize to zero
@@ -173,8 +308,7 @@ impl GenerateKoopa for Decl {
                     let alloc_ptr = ctx.new_global_value().global_alloc(init);
                     // No need to append scope level to global variable names
                     ctx.set_value_name(alloc_ptr, format!("@{}", var_name));
-                    ctx.symbol_table
-                        .insert(var_name.clone(), SymbolInfo::Variable(alloc_ptr));
+                    ctx.declare_symbol(var_name, SymbolInfo::Variable(alloc_ptr));
                     return;
                 }
                 // Local variable
@@ -183,6 +317,11 @@ impl GenerateKoopa for Decl {
                 let unique_name = format!("@{}_{}", var_name, ctx.symbol_table.level());
                 ctx.set_value_name(alloc_ptr, unique_name);
 
+                if !initialize {
+                    ctx.add_entry_inst(alloc_ptr);
+                    ctx.declare_symbol(var_name, SymbolInfo::Variable(alloc_ptr));
+                    return;
+                }
                 ctx.add_inst(alloc_ptr);
                 // If there is an initializer, calculate and store the value
                 if let Some(expr) = init_expr {
@@ -190,8 +329,7 @@ impl GenerateKoopa for Decl {
                     let store_inst = ctx.new_value().store(expr_value, alloc_ptr);
                     ctx.add_inst(store_inst);
                 }
-                ctx.symbol_table
-                    .insert(var_name.clone(), SymbolInfo::Variable(alloc_ptr));
+                ctx.declare_symbol(var_name, SymbolInfo::Variable(alloc_ptr));
             }
             Decl::ConstArray {
                 var_type,
@@ -205,19 +343,26 @@ impl GenerateKoopa for Decl {
                 dims,
                 init_list,
             } => {
-                let shape: Vec<usize> = dims
-                    .iter()
-                    .map(|dim_expr| dim_expr.compute_constexpr(ctx) as usize)
-                    .collect();
+                let shape = compute_array_shape(var_name, dims, 1, ctx);
                 let elem_type = match var_type {
                     DataType::Int => Type::get_i32(),
                 };
                 let array_type = build_array_type(elem_type.clone(), &shape);
+                let is_const = matches!(self, Decl::ConstArray { .. });
+                let make_symbol = if is_const {
+                    SymbolInfo::ConstArray
+                } else {
+                    SymbolInfo::Variable
+                };
 
                 if ctx.symbol_table.is_global_scope() {
+                    let mut const_values = None;
                     let init = if let Some(_init_list) = init_list {
                         let mut helper = ArrayInitHelper::new(ctx, &shape);
                         let flat_vals = helper.flatten_init_list(init_list);
+                        if is_const {
+                            const_values = Some(flat_vals.clone());
+                        }
                         helper.generate_global_init(flat_vals)
                     } else {
                         // Default initialize to zero
@@ -227,24 +372,47 @@ impl GenerateKoopa for Decl {
                     let alloc_ptr = ctx.new_global_value().global_alloc(init);
                     // No need to append scope level to global variable names
                     ctx.set_value_name(alloc_ptr, format!("@{}", var_name));
-                    ctx.symbol_table
-                        .insert(var_name.clone(), SymbolInfo::Variable(alloc_ptr));
+                    if is_const {
+                        let flat_vals = const_values.unwrap_or_else(|| {
+                            let zero = ctx.new_integer_value(0);
+                            vec![zero; shape.iter().product()]
+                        });
+                        ctx.set_const_array_values(alloc_ptr, &shape, &flat_vals);
+                    }
+                    ctx.declare_symbol(var_name, make_symbol(alloc_ptr));
                 } else {
                     // Local array
                     let alloc_ptr = ctx.new_value().alloc(array_type.clone());
                     // Koopa IR value names must be unique
                     let unique_name = format!("@{}_{}", var_name, ctx.symbol_table.level());
                     ctx.set_value_name(alloc_ptr, unique_name);
-                    ctx.add_inst(alloc_ptr);
+                    if initialize {
+                        ctx.add_inst(alloc_ptr);
+                    } else {
+                        ctx.add_entry_inst(alloc_ptr);
+                    }
 
                     // If there is an initializer, calculate and store the values
-                    if let Some(_init_list) = init_list {
+                    // (the values of a constant array are needed even if the
+                    // stores are skipped)
+                    if init_list.is_some() && (initialize || is_const) {
                         let mut helper = ArrayInitHelper::new(ctx, &shape);
+                        if is_const {
+                            helper = helper.constant();
+                        }
                         let flat_vals = helper.flatten_init_list(init_list);
-                        helper.generate_local_init(alloc_ptr, &flat_vals);
+                        if initialize {
+                            helper.generate_local_init(alloc_ptr, &flat_vals);
+                        }
+                        if is_const {
+                            ctx.set_const_array_values(alloc_ptr, &shape, &flat_vals);
+                        }
+                    } else if is_const {
+                        let zero = ctx.new_integer_value(0);
+                        let flat_vals = vec![zero; shape.iter().product()];
+                        ctx.set_const_array_values(alloc_ptr, &shape, &flat_vals);
                     }
-                    ctx.symbol_table
-                        .insert(var_name.clone(), SymbolInfo::Variable(alloc_ptr));
+                    ctx.declare_symbol(var_name, make_symbol(alloc_ptr));
                 }
             }
         };
@@ -252,43 +420,76 @@ impl GenerateKoopa for Decl {
 }
 
 impl GenerateKoopa for Stmt {
-    fn generate(&self, ctx: &mut KoopaContext) -> () {
+    fn generate(&self, ctx: &mut KoopaContext) {
         match self {
             Stmt::Return { expr } => {
-                if let Some(expr) = expr {
-                    let value: Value = expr.generate(ctx);
-                    let inst: Value = ctx.new_value().ret(Some(value));
-                    ctx.add_inst(inst);
-                }
+                let returns_value = !ctx.current_func_return_type().is_unit();
+                let value = match (expr, returns_value) {
+                    (Some(expr), true) => Some(expr.generate(ctx)),
+                    (None, false) => None,
+                    (Some(_), false) => {
+                        let name = ctx.current_func().name()[1..].to_string();
+                        ctx.report_error(CompileError::ReturnValueFromVoid(name));
+                        None
+                    }
+                    (None, true) => {
+                        let name = ctx.current_func().name()[1..].to_string();
+                        ctx.report_error(CompileError::MissingReturnValue(name));
+                        Some(ctx.new_value().integer(0))
+                    }
+                };
+                let inst: Value = ctx.new_value().ret(value);
+                ctx.add_inst(inst);
             }
 
-            Stmt::Assign { lval, expr } => {
+            Stmt::Assign { lval, op, expr } => {
                 let Expr::LVal { name, indices } = lval else {
                     panic!("Left-hand side of assignment must be an LVal");
                 };
-                let symbol = ctx.symbol_table.lookup(name);
-
-                let val = match symbol {
-                    SymbolInfo::Variable(val) => val,
-                    SymbolInfo::ConstVariable(_val) => {
-                        panic!("Cannot assign to constant variable '{}'", name)
+                let value = expr.generate(ctx);
+                let val = match ctx.lookup_symbol(name) {
+                    Some(SymbolInfo::Variable(val)) => val,
+                    Some(SymbolInfo::ConstVariable(_)) => {
+                        ctx.report_error(CompileError::AssignToConst(name.clone()));
+                        return;
                     }
-                    SymbolInfo::Function(_) => panic!("Function cannot be used as LVal"),
+                    Some(SymbolInfo::ConstArray(_)) => {
+                        ctx.report_error(CompileError::AssignToConstArray(name.clone()));
+                        return;
+                    }
+                    Some(SymbolInfo::Function(_)) => {
+                        ctx.report_error(CompileError::AssignToFunction(name.clone()));
+                        return;
+                    }
+                    None => return,
                 };
 
-                let value = expr.generate(ctx);
                 let ptr = if let Some(indices) = indices {
                     Expr::generate_lval_addr(val, indices, ctx)
                 } else {
                     val
                 };
+                // `a[i] += x` loads and stores through the same address,
+                // so the indices are evaluated once
+                let value = match op {
+                    Some(op) => {
+                        let old = ctx.new_value().load(ptr);
+                        ctx.add_inst(old);
+                        let koopa_op =
+                            map_binary_op(*op).expect("Compound assignments are arithmetic");
+                        let inst = ctx.new_value().binary(koopa_op, old, value);
+                        ctx.add_inst(inst);
+                        inst
+                    }
+                    None => value,
+                };
                 let store_inst = ctx.new_value().store(value, ptr);
                 ctx.add_inst(store_inst);
             }
 
             Stmt::Expression { expr } => {
                 if let Some(expr) = expr {
-                    let _ = expr.generate(ctx);
+                    expr.generate_discarded(ctx);
                 }
             }
 
@@ -326,19 +527,49 @@ impl GenerateKoopa for Stmt {
                 //   jump end_bb
                 // end_bb:
                 //   ...
+                //
+                // If both arms end with return/break/continue, end_bb is
+                // unreachable and is not emitted at all. The current basic
+                // block then stays terminated, so the enclosing block stops
+                // generating the (dead) statements that follow.
                 let cond_value = cond.generate(ctx);
-                let has_else: bool = else_body.is_some();
+                // A constant condition (e.g. `1 < 2`) selects one arm at
+                // compile time. The other arm is dropped, unless it contains
+                // a label that a `goto` may still jump to. The dropped arm
+                // is still generated and discarded, so its errors are reported
+                if let ValueKind::Integer(int) = ctx.get_value_kind(cond_value) {
+                    let taken_then = int.value() != 0;
+                    let dropped = if taken_then {
+                        else_body.as_deref()
+                    } else {
+                        Some(&**then_body)
+                    };
+                    if !dropped.is_some_and(|stmt| stmt.contains_label()) {
+                        if taken_then {
+                            then_body.generate(ctx);
+                            if let Some(else_body) = else_body {
+                                ctx.discard(|ctx| else_body.generate(ctx));
+                            }
+                        } else {
+                            ctx.discard(|ctx| then_body.generate(ctx));
+                            if let Some(else_body) = else_body {
+                                else_body.generate(ctx);
+                            }
+                        }
+                        return;
+                    }
+                }
+
                 let then_bb = ctx.new_bb("%then");
-                let end_bb = ctx.new_bb("%end");
-                let else_bb = if has_else {
-                    ctx.new_bb("%else")
+                let (else_bb, mut end_bb) = if else_body.is_some() {
+                    (ctx.new_bb("%else"), None)
                 } else {
-                    end_bb
+                    // If no else body, jump to end_bb directly
+                    let end_bb = ctx.new_bb("%end");
+                    (end_bb, Some(end_bb))
                 };
 
-                let branch_inst = ctx.new_value().branch(
-                    cond_value, then_bb, else_bb, // If no else body, jump to end_bb directly
-                );
+                let branch_inst = ctx.new_value().branch(cond_value, then_bb, else_bb);
                 ctx.add_inst(branch_inst);
 
                 // Then body
@@ -347,10 +578,9 @@ impl GenerateKoopa for Stmt {
                 then_body.generate(ctx);
                 // Check if then_bb already ends with a jump/branch/ret
                 // If not, we need to add a jump to the end_bb
-                // The only case then_bb is terminated is when then_body ends
-                // with a return statement
                 if !ctx.is_current_bb_terminated() {
-                    let jump_to_merge_from_then = ctx.new_value().jump(end_bb);
+                    let target = *end_bb.get_or_insert_with(|| ctx.new_bb("%end"));
+                    let jump_to_merge_from_then = ctx.new_value().jump(target);
                     ctx.add_inst(jump_to_merge_from_then);
                 }
                 // Else body
@@ -362,14 +592,17 @@ impl GenerateKoopa for Stmt {
                     // even if they are adjacent, because Koopa IR basic blocks
                     // must end with ret/branch/jump instructions
                     if !ctx.is_current_bb_terminated() {
-                        let jump_to_merge_from_else = ctx.new_value().jump(end_bb);
+                        let target = *end_bb.get_or_insert_with(|| ctx.new_bb("%end"));
+                        let jump_to_merge_from_else = ctx.new_value().jump(target);
                         ctx.add_inst(jump_to_merge_from_else);
                     }
                 }
 
                 // End block
-                ctx.add_bb(end_bb);
-                ctx.set_current_bb(end_bb);
+                if let Some(end_bb) = end_bb {
+                    ctx.add_bb(end_bb);
+                    ctx.set_current_bb(end_bb);
+                }
             }
 
             Stmt::While { cond, body } => {
@@ -384,6 +617,8 @@ impl GenerateKoopa for Stmt {
                 //   jump cond_bb
                 // end_bb:
                 //   ...
+                // An empty body (`while (c);` or `while (c) {}`) leaves
+                // body_bb with only the jump back to cond_bb
 
                 let cond_bb = ctx.new_bb("%while_cond");
                 let body_bb = ctx.new_bb("%while_body");
@@ -408,27 +643,185 @@ impl GenerateKoopa for Stmt {
                 ctx.enter_loop(end_bb, cond_bb);
                 body.generate(ctx);
                 ctx.exit_loop();
-                // After body, jump back to condition check
-                // The only case current_bb is terminated is when body ends
-                // with a return statement
+                // After body, jump back to condition check, unless the body
+                // already ended with return/break/continue (e.g. an early
+                // `return` inside the loop): a jump after the terminator
+                // would make the block invalid
+                if !ctx.is_current_bb_terminated() {
+                    let jump_to_cond = ctx.new_value().jump(cond_bb);
+                    ctx.add_inst(jump_to_cond);
+                }
+
+                // End block
+                ctx.add_bb(end_bb);
+                ctx.set_current_bb(end_bb);
+            }
+
+            Stmt::DoWhile { body, cond } => {
+                // do { body } while (cond);
+                // will be translated to:
+                // jump body_bb
+                // body_bb:
+                //   body
+                //   jump cond_bb
+                // cond_bb:
+                //   cond calculation
+                //   br cond, body_bb, end_bb
+                // end_bb:
+                //   ...
+                // `continue` jumps to cond_bb, so the condition is still
+                // checked
+
+                let body_bb = ctx.new_bb("%do_body");
+                let cond_bb = ctx.new_bb("%do_cond");
+                let end_bb = ctx.new_bb("%do_end");
+
+                let initial_jump = ctx.new_value().jump(body_bb);
+                ctx.add_inst(initial_jump);
+
+                // Body block
+                ctx.add_bb(body_bb);
+                ctx.set_current_bb(body_bb);
+                ctx.enter_loop(end_bb, cond_bb);
+                body.generate(ctx);
+                ctx.exit_loop();
                 if !ctx.is_current_bb_terminated() {
                     let jump_to_cond = ctx.new_value().jump(cond_bb);
                     ctx.add_inst(jump_to_cond);
                 }
 
+                // Condition block
+                ctx.add_bb(cond_bb);
+                ctx.set_current_bb(cond_bb);
+                let cond_value = cond.generate(ctx);
+                let branch_inst = ctx.new_value().branch(cond_value, body_bb, end_bb);
+                ctx.add_inst(branch_inst);
+
+                // End block
+                ctx.add_bb(end_bb);
+                ctx.set_current_bb(end_bb);
+            }
+
+            Stmt::For {
+                init,
+                cond,
+                step,
+                body,
+            } => {
+                // for (init; cond; step) { body }
+                // will be translated to:
+                // init
+                // jump cond_bb
+                // cond_bb:
+                //   cond calculation
+                //   br cond, body_bb, end_bb
+                // body_bb:
+                //   body
+                //   jump step_bb
+                // step_bb:
+                //   step
+                //   jump cond_bb
+                // end_bb:
+                //   ...
+                // Without a condition, cond_bb jumps to body_bb directly.
+                // `continue` jumps to step_bb, not cond_bb
+
+                // The scope of the variables declared by init
+                ctx.symbol_table.enter_scope();
+                for item in init {
+                    match item {
+                        BlockItem::Stmt(stmt) => stmt.generate(ctx),
+                        BlockItem::Decl(decl) => decl.generate(ctx),
+                    }
+                }
+
+                let cond_bb = ctx.new_bb("%for_cond");
+                let body_bb = ctx.new_bb("%for_body");
+                let step_bb = ctx.new_bb("%for_step");
+                let end_bb = ctx.new_bb("%for_end");
+
+                let initial_jump = ctx.new_value().jump(cond_bb);
+                ctx.add_inst(initial_jump);
+
+                // Condition block
+                ctx.add_bb(cond_bb);
+                ctx.set_current_bb(cond_bb);
+                let to_body = match cond {
+                    Some(cond) => {
+                        let cond_value = cond.generate(ctx);
+                        ctx.new_value().branch(cond_value, body_bb, end_bb)
+                    }
+                    None => ctx.new_value().jump(body_bb),
+                };
+                ctx.add_inst(to_body);
+
+                // Body block
+                ctx.add_bb(body_bb);
+                ctx.set_current_bb(body_bb);
+                ctx.enter_loop(end_bb, step_bb);
+                body.generate(ctx);
+                ctx.exit_loop();
+                if !ctx.is_current_bb_terminated() {
+                    let jump_to_step = ctx.new_value().jump(step_bb);
+                    ctx.add_inst(jump_to_step);
+                }
+
+                // Step block
+                ctx.add_bb(step_bb);
+                ctx.set_current_bb(step_bb);
+                if let Some(step) = step {
+                    step.generate(ctx);
+                }
+                let jump_to_cond = ctx.new_value().jump(cond_bb);
+                ctx.add_inst(jump_to_cond);
+
                 // End block
                 ctx.add_bb(end_bb);
                 ctx.set_current_bb(end_bb);
+                ctx.symbol_table.exit_scope();
             }
 
             Stmt::Break => {
-                let target = ctx.get_current_loop_break_target();
+                let Some(target) = ctx.get_current_loop_break_target() else {
+                    ctx.report_error(CompileError::BreakOutsideLoop);
+                    return;
+                };
                 let jump_inst = ctx.new_value().jump(target);
                 ctx.add_inst(jump_inst);
             }
 
             Stmt::Continue => {
-                let target = ctx.get_current_loop_continue_target();
+                let Some(target) = ctx.get_current_loop_continue_target() else {
+                    ctx.report_error(CompileError::ContinueOutsideLoop);
+                    return;
+                };
+                let jump_inst = ctx.new_value().jump(target);
+                ctx.add_inst(jump_inst);
+            }
+
+            Stmt::Label(name) => {
+                // label:
+                // will be translated to:
+                //   jump label_bb (falling through from the previous code)
+                // label_bb:
+                //   ...
+                //
+                // Jumping over a declaration with `goto` leaves the variable
+                // uninitialized, as in C: all locals are allocated in the
+                // stack frame up front, only their initialization is skipped
+                let Some(label_bb) = ctx.define_label(name) else {
+                    return;
+                };
+                if !ctx.is_current_bb_terminated() {
+                    let jump_inst = ctx.new_value().jump(label_bb);
+                    ctx.add_inst(jump_inst);
+                }
+                ctx.add_bb(label_bb);
+                ctx.set_current_bb(label_bb);
+            }
+
+            Stmt::Goto(label) => {
+                let target = ctx.label_bb(label);
                 let jump_inst = ctx.new_value().jump(target);
                 ctx.add_inst(jump_inst);
             }
@@ -436,11 +829,75 @@ impl GenerateKoopa for Stmt {
     }
 }
 
+impl Stmt {
+    /// Whether the statement contains a label, i.e. whether it can be
+    /// entered by a `goto` even when the code before it is unreachable
+    fn contains_label(&self) -> bool {
+        match self {
+            Stmt::Label(_) => true,
+            Stmt::Block { block } => block.items.iter().any(|item| match item {
+                BlockItem::Stmt(stmt) => stmt.contains_label(),
+                BlockItem::Decl(_) => false,
+            }),
+            Stmt::If {
+                then_body,
+                else_body,
+                ..
+            } => {
+                then_body.contains_label() || else_body.as_ref().is_some_and(|s| s.contains_label())
+            }
+            Stmt::While { body, .. } | Stmt::DoWhile { body, .. } | Stmt::For { body, .. } => {
+                body.contains_label()
+            }
+            _ => false,
+        }
+    }
+}
+
 impl Expr {
+    /// Whether the expression always evaluates to 0 or 1: comparisons,
+    /// logical operators and `!`, or a choice between such expressions
+    fn is_boolean(&self) -> bool {
+        match self {
+            Expr::Binary { op, .. } => !is_arithmetic(*op),
+            Expr::Unary {
+                op: UnaryOp::Not, ..
+            } => true,
+            Expr::Number(n) => *n == 0 || *n == 1,
+            Expr::Comma { rhs, .. } => rhs.is_boolean(),
+            Expr::Ternary { then, else_, .. } => then.is_boolean() && else_.is_boolean(),
+            _ => false,
+        }
+    }
+
+    /// Whether evaluating the expression when the program would not have
+    /// is unobservable: it calls no function, reads no array element (the
+    /// index may be out of bounds, as in `i < n && a[i]`) and does not
+    /// divide by a value that may be zero
+    fn is_speculatable(&self) -> bool {
+        match self {
+            Expr::Binary {
+                op: AstBinaryOp::Div | AstBinaryOp::Mod,
+                lhs,
+                rhs,
+            } => lhs.is_speculatable() && matches!(**rhs, Expr::Number(n) if n != 0 && n != -1),
+            Expr::Binary { lhs, rhs, .. } | Expr::Comma { lhs, rhs } => {
+                lhs.is_speculatable() && rhs.is_speculatable()
+            }
+            Expr::Unary { expr, .. } => expr.is_speculatable(),
+            Expr::Ternary { cond, then, else_ } => {
+                cond.is_speculatable() && then.is_speculatable() && else_.is_speculatable()
+            }
+            Expr::LVal { indices, .. } => indices.is_none(),
+            Expr::Number(_) => true,
+            Expr::Call { .. } => false,
+        }
+    }
+
     /// Generate the address of an array indexing operation (i.e. the getptr/getelemptr instructions)
     pub fn generate_lval_addr(
         mut ptr: Value,
-        indices: &Vec<Expr>,
+        indices: &[Expr],
         ctx: &mut KoopaContext,
     ) -> Value {
         // Mark whether to use getptr for the first dimension
@@ -468,29 +925,42 @@ impl Expr {
         ptr
     }
 
-    pub fn compute_constexpr(&self, ctx: &KoopaContext) -> i32 {
-        match self {
+    /// Evaluates a constant expression at compile time.
+    /// Returns an error message if the expression is not constant.
+    pub fn compute_constexpr(&self, ctx: &KoopaContext) -> Result<i32, String> {
+        let value = match self {
             Expr::Number(n) => *n,
             Expr::Unary { op, expr } => {
-                let val = expr.compute_constexpr(ctx);
+                let val = expr.compute_constexpr(ctx)?;
                 match op {
                     UnaryOp::Pos => val,
-                    UnaryOp::Neg => -val,
-                    // Note that `!val` is bitwise NOT instead of logical NOT
+                    UnaryOp::Neg => val.wrapping_neg(),
+                    // Logical NOT, matching `eq x, 0` in `generate`. Rust's
+                    // `!val` would be bitwise NOT
                     UnaryOp::Not => (val == 0) as i32,
+                    UnaryOp::BitNot => !val,
                 }
             }
             Expr::Binary { op, lhs, rhs } => {
-                let left = lhs.compute_constexpr(ctx);
-                let right = rhs.compute_constexpr(ctx);
+                let left = lhs.compute_constexpr(ctx)?;
+                let right = rhs.compute_constexpr(ctx)?;
+                // Arithmetic wraps around like the generated code does
                 match op {
-                    AstBinaryOp::Add => left + right,
-                    AstBinaryOp::Sub => left - right,
-                    AstBinaryOp::Mul => left * right,
-
-                    // [TODO]: Check if right == 0
-                    AstBinaryOp::Div => left / right,
-                    AstBinaryOp::Mod => left % right,
+                    AstBinaryOp::Add => left.wrapping_add(right),
+                    AstBinaryOp::Sub => left.wrapping_sub(right),
+                    AstBinaryOp::Mul => left.wrapping_mul(right),
+
+                    AstBinaryOp::Div | AstBinaryOp::Mod if right == 0 => {
+                        let symbol = if let AstBinaryOp::Div = op { "/" } else { "%" };
+                        return Err(format!(
+                            "division by zero in constant expression `{} {} 0`",
+                            left, symbol
+                        ));
+                    }
+                    // `i32::MIN / -1` is `i32::MIN` and `i32::MIN % -1` is 0,
+                    // like the RISC-V `div` and `rem` instructions
+                    AstBinaryOp::Div => left.wrapping_div(right),
+                    AstBinaryOp::Mod => left.wrapping_rem(right),
 
                     AstBinaryOp::Eq => (left == right) as i32,
                     AstBinaryOp::Neq => (left != right) as i32,
@@ -501,39 +971,155 @@ impl Expr {
 
                     AstBinaryOp::And => ((left != 0) && (right != 0)) as i32,
                     AstBinaryOp::Or => ((left != 0) || (right != 0)) as i32,
+
+                    AstBinaryOp::BitAnd => left & right,
+                    AstBinaryOp::BitOr => left | right,
+                    AstBinaryOp::BitXor => left ^ right,
+                    // Shift amounts are taken modulo 32, like `sll`/`sra`
+                    AstBinaryOp::Shl => left.wrapping_shl(right as u32),
+                    AstBinaryOp::Shr => left.wrapping_shr(right as u32),
                 }
             }
             // Constant variables are also treated as LVal here
             Expr::LVal { name, indices } => {
-                let addr: SymbolInfo = ctx.symbol_table.lookup(name);
-
-                let SymbolInfo::ConstVariable(var) = addr else {
-                    panic!("Cannot use non-constant variable in constant expression");
+                let Some(symbol) = ctx.symbol_table.lookup(name) else {
+                    return Err(format!("use of undeclared identifier '{}'", name));
+                };
+                if let (SymbolInfo::ConstArray(alloc), Some(indices)) = (symbol, indices) {
+                    let indices = indices
+                        .iter()
+                        .map(|index| index.compute_constexpr(ctx))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    return ctx.const_array_element(alloc, name, &indices);
+                }
+                let SymbolInfo::ConstVariable(var) = symbol else {
+                    return Err(format!(
+                        "'{}' is not a constant and cannot be used in a constant expression",
+                        name
+                    ));
                 };
                 if indices.is_some() {
-                    panic!("Constant variable cannot be an array in constant expression");
+                    return Err(format!(
+                        "constant '{}' is not an array and cannot be indexed",
+                        name
+                    ));
                 }
                 let v = ctx.get_value_kind(var);
                 let ValueKind::Integer(n) = v else {
-                    panic!("Constant variable does not hold an integer value");
+                    unreachable!("Constant variable does not hold an integer value");
                 };
                 n.value()
             }
-            Expr::Call {
-                func_name: _,
-                args: _,
-            } => {
-                panic!("Constant variable cannot have function calls");
+            Expr::Call { func_name, args } if ctx.is_sizeof(func_name) => {
+                Expr::compute_sizeof(args, ctx)?
+            }
+            Expr::Call { func_name, .. } => {
+                return Err(format!(
+                    "call to '{}' cannot be used in a constant expression",
+                    func_name
+                ));
+            }
+            Expr::Comma { .. } => {
+                return Err("comma expression cannot be used in a constant expression".to_string());
+            }
+            // Only the selected operand has to be constant, e.g.
+            // `1 ? 2 : x` is 2
+            Expr::Ternary { cond, then, else_ } => {
+                if cond.compute_constexpr(ctx)? != 0 {
+                    then.compute_constexpr(ctx)?
+                } else {
+                    else_.compute_constexpr(ctx)?
+                }
+            }
+        };
+        Ok(value)
+    }
+
+    /// Like `compute_constexpr_or_report`, for the initializer of the
+    /// constant or global variable `name`, which the error names
+    fn compute_initializer_or_report(&self, name: &str, ctx: &mut KoopaContext) -> i32 {
+        self.compute_constexpr(ctx).unwrap_or_else(|reason| {
+            ctx.report_error(CompileError::InvalidInitializer {
+                name: name.to_string(),
+                reason,
+            });
+            0
+        })
+    }
+
+    /// Byte size of `sizeof(a)`, `sizeof(a[0])`, ... (see
+    /// `FrontendOptions::sizeof`). Only the number of indices matters,
+    /// they are never evaluated
+    fn compute_sizeof(args: &[Expr], ctx: &KoopaContext) -> Result<i32, String> {
+        let [Expr::LVal { name, indices }] = args else {
+            return Err("sizeof expects a variable, optionally indexed".to_string());
+        };
+        let depth = indices.as_ref().map_or(0, Vec::len);
+        let mut ty = match ctx.symbol_table.lookup(name) {
+            Some(SymbolInfo::ConstVariable(_)) => Type::get_i32(),
+            Some(SymbolInfo::Variable(ptr) | SymbolInfo::ConstArray(ptr)) => {
+                match ctx.get_value_type(ptr).kind() {
+                    TypeKind::Pointer(declared) => declared.clone(),
+                    _ => unreachable!("Variables are always stored behind a pointer"),
+                }
+            }
+            Some(SymbolInfo::Function(_)) => {
+                return Err(format!("cannot take the size of function '{}'", name))
             }
+            None => return Err(format!("use of undeclared identifier '{}'", name)),
+        };
+        // Strip one array (or decayed pointer) level per index
+        for _ in 0..depth {
+            ty = match ty.kind() {
+                TypeKind::Array(elem, _) | TypeKind::Pointer(elem) => elem.clone(),
+                _ => return Err(format!("subscripted value '{}' is not an array", name)),
+            };
         }
+        Ok(ty.size() as i32)
+    }
+
+    /// Evaluates a constant expression, reporting an error and falling back
+    /// to 0 if it is not constant
+    pub fn compute_constexpr_or_report(&self, ctx: &mut KoopaContext) -> i32 {
+        self.compute_constexpr(ctx).unwrap_or_else(|reason| {
+            ctx.report_error(CompileError::NotConstant(reason));
+            0
+        })
     }
 
     pub fn generate(&self, ctx: &mut KoopaContext) -> Value {
         match self {
             Expr::Number(n) => ctx.new_value().integer(*n),
             Expr::Binary { op, lhs, rhs } => {
+                // Fold constant comparisons and logical operations, e.g.
+                // `1 < 2` or `3 == 3 && 0`, so that constant conditions can
+                // be resolved at compile time
+                if !is_arithmetic(*op) {
+                    if let Ok(value) = self.compute_constexpr(ctx) {
+                        return ctx.new_value().integer(value);
+                    }
+                }
                 let lhs_value = lhs.generate(ctx);
 
+                // Both operands are already 0/1 and evaluating `rhs` even
+                // when it would have been skipped is unobservable, so the
+                // bitwise operation gives the result without branching,
+                // e.g. `a < b && c < d`
+                let branchless = matches!(op, AstBinaryOp::And | AstBinaryOp::Or)
+                    && lhs.is_boolean()
+                    && rhs.is_boolean()
+                    && rhs.is_speculatable();
+                if branchless {
+                    let rhs_value = rhs.generate(ctx);
+                    let koopa_op = match op {
+                        AstBinaryOp::And => KoopaBinaryOp::And,
+                        _ => KoopaBinaryOp::Or,
+                    };
+                    let inst = ctx.new_value().binary(koopa_op, lhs_value, rhs_value);
+                    ctx.add_inst(inst);
+                    return inst;
+                }
+
                 match op {
                     AstBinaryOp::And => {
                         // Short-circuiting Logical AND (&&)
@@ -660,14 +1246,28 @@ impl Expr {
                     ctx.add_inst(inst);
                     inst
                 }
+                UnaryOp::BitNot => {
+                    // Koopa IR has no `not`: ~x == x ^ -1
+                    let value = expr.generate(ctx);
+                    let all_ones = ctx.new_value().integer(-1);
+                    let inst = ctx.new_value().binary(KoopaBinaryOp::Xor, value, all_ones);
+                    ctx.add_inst(inst);
+                    inst
+                }
             },
 
             Expr::LVal { name, indices } => {
-                let symbol = ctx.symbol_table.lookup(name);
-
-                let val = match symbol {
-                    SymbolInfo::Variable(val) => val,
-                    SymbolInfo::ConstVariable(val) => {
+                let val = match ctx.lookup_symbol(name) {
+                    Some(SymbolInfo::ConstArray(val)) => {
+                        // Elements of a const array read with constant
+                        // indices are folded, e.g. `M[1][0]`
+                        if let Ok(value) = self.compute_constexpr(ctx) {
+                            return ctx.new_value().integer(value);
+                        }
+                        val
+                    }
+                    Some(SymbolInfo::Variable(val)) => val,
+                    Some(SymbolInfo::ConstVariable(val)) => {
                         // Koopa IR library does not allow global constant values
                         // to be operated directly, for I don't know why...
                         // This is a workaround to load the actual value into
@@ -683,7 +1283,11 @@ impl Expr {
                             return val;
                         }
                     }
-                    SymbolInfo::Function(_) => panic!("Function cannot be used as LVal"),
+                    Some(SymbolInfo::Function(_)) => {
+                        ctx.report_error(CompileError::FunctionAsValue(name.clone()));
+                        return ctx.new_value().integer(0);
+                    }
+                    None => return ctx.new_value().integer(0),
                 };
 
                 let ptr = if let Some(indices) = indices {
@@ -725,27 +1329,148 @@ impl Expr {
                 }
             }
 
+            Expr::Call { func_name, args } if ctx.is_sizeof(func_name) => {
+                let size = self.compute_constexpr_or_report(ctx);
+                ctx.new_value().integer(size)
+            }
             Expr::Call { func_name, args } => {
-                let symbol_info = ctx.symbol_table.lookup(func_name);
-                let SymbolInfo::Function(func) = symbol_info else {
-                    panic!("Symbol is not a function");
-                };
+                let call = Expr::generate_call(func_name, args, ctx);
+                if ctx.get_value_type(call).is_unit() {
+                    ctx.report_error(CompileError::VoidCallAsValue(func_name.clone()));
+                    return ctx.new_value().integer(0);
+                }
+                call
+            }
+
+            Expr::Comma { lhs, rhs } => {
+                lhs.generate_discarded(ctx);
+                rhs.generate(ctx)
+            }
+
+            Expr::Ternary { cond, then, else_ } => {
+                // Logic: if (cond != 0) { result = then; } else { result = else_; }
+                // Like `&&` and `||`, the result goes through a temporary
+                // variable, and only the selected operand is evaluated
+                let result_ptr = ctx.new_value().alloc(Type::get_i32());
+                ctx.add_inst(result_ptr);
 
-                let mut arg_values = Vec::new();
-                for arg in args {
-                    arg_values.push(arg.generate(ctx));
+                let cond_value = cond.generate(ctx);
+                let then_bb = ctx.new_bb("%ternary_then");
+                let else_bb = ctx.new_bb("%ternary_else");
+                let end_bb = ctx.new_bb("%ternary_end");
+                let branch = ctx.new_value().branch(cond_value, then_bb, else_bb);
+                ctx.add_inst(branch);
+
+                for (bb, operand) in [(then_bb, then), (else_bb, else_)] {
+                    ctx.add_bb(bb);
+                    ctx.set_current_bb(bb);
+                    let value = operand.generate(ctx);
+                    let store = ctx.new_value().store(value, result_ptr);
+                    ctx.add_inst(store);
+                    let jump = ctx.new_value().jump(end_bb);
+                    ctx.add_inst(jump);
                 }
 
-                let call_inst = ctx.new_value().call(func, arg_values);
-                ctx.add_inst(call_inst);
+                // End block
+                ctx.add_bb(end_bb);
+                ctx.set_current_bb(end_bb);
+                let result = ctx.new_value().load(result_ptr);
+                ctx.add_inst(result);
+                result
+            }
+        }
+    }
+
+    /// Generates an expression whose value is not used, e.g. an expression
+    /// statement or the left operand of a comma. Unlike `generate`, this
+    /// allows calls to void functions
+    pub fn generate_discarded(&self, ctx: &mut KoopaContext) {
+        match self {
+            Expr::Call { func_name, args } => {
+                Expr::generate_call(func_name, args, ctx);
+            }
+            Expr::Comma { lhs, rhs } => {
+                lhs.generate_discarded(ctx);
+                rhs.generate_discarded(ctx);
+            }
+            _ => {
+                self.generate(ctx);
+            }
+        }
+    }
+
+    /// Generates a function call. The result is a unit value for `void`
+    /// functions, so callers must check it before using it as an operand
+    fn generate_call(func_name: &str, args: &[Expr], ctx: &mut KoopaContext) -> Value {
+        let mut arg_values = Vec::new();
+        for arg in args {
+            arg_values.push(arg.generate(ctx));
+        }
 
-                // `void` functions return a unit value
-                call_inst
+        let func = match ctx.lookup_symbol(func_name) {
+            Some(SymbolInfo::Function(func)) => func,
+            Some(_) => {
+                ctx.report_error(CompileError::NotAFunction(func_name.to_string()));
+                return ctx.new_value().integer(0);
             }
+            None => return ctx.new_value().integer(0),
+        };
+        let TypeKind::Function(param_types, _) = ctx.program.func(func).ty().kind() else {
+            unreachable!("Function must have a function type");
+        };
+        let param_count = param_types.len();
+        if param_count != arg_values.len() {
+            ctx.report_error(CompileError::ArgumentCount {
+                function: func_name.to_string(),
+                expected: param_count,
+                found: arg_values.len(),
+            });
+            return ctx.new_value().integer(0);
         }
+
+        let call_inst = ctx.new_value().call(func, arg_values);
+        ctx.add_inst(call_inst);
+
+        // `void` functions return a unit value
+        call_inst
     }
 }
 
+/// Evaluates the dimensions of the declaration of array `name`, where
+/// `dims[0]` is its `first_dim`-th dimension (counting from 1).
+/// Variable-length arrays are not supported, so a dimension that is not a
+/// constant expression is reported, like one that is not positive (both are
+/// treated as 1)
+fn compute_array_shape(
+    name: &str,
+    dims: &[Expr],
+    first_dim: usize,
+    ctx: &mut KoopaContext,
+) -> Vec<usize> {
+    dims.iter()
+        .enumerate()
+        .map(|(i, dim_expr)| match dim_expr.compute_constexpr(ctx) {
+            Ok(size) if size > 0 => size as usize,
+            Ok(size) => {
+                ctx.report_error(CompileError::NonPositiveArraySize {
+                    name: name.to_string(),
+                    dimension: first_dim + i,
+                    size,
+                });
+                1
+            }
+            Err(reason) => {
+                ctx.report_error(CompileError::ArraySizeNotConstant {
+                    name: name.to_string(),
+                    dimension: first_dim + i,
+                    reason,
+                });
+                1
+            }
+        })
+        .collect()
+}
+
 /// Unwraps an InitList to get the contained Expr for variable declarations.
 /// The InitList must contain exactly one expression
 fn unwrap_init_list(init_list: &InitList) -> &Expr {
@@ -777,7 +1502,30 @@ fn map_binary_op(op: AstBinaryOp) -> Option<KoopaBinaryOp> {
         AstBinaryOp::Gt => Some(KoopaBinaryOp::Gt),
         AstBinaryOp::Leq => Some(KoopaBinaryOp::Le),
         AstBinaryOp::Geq => Some(KoopaBinaryOp::Ge),
+        AstBinaryOp::BitAnd => Some(KoopaBinaryOp::And),
+        AstBinaryOp::BitOr => Some(KoopaBinaryOp::Or),
+        AstBinaryOp::BitXor => Some(KoopaBinaryOp::Xor),
+        AstBinaryOp::Shl => Some(KoopaBinaryOp::Shl),
+        AstBinaryOp::Shr => Some(KoopaBinaryOp::Sar),
         // And/Or are handled separately in the main logic
         AstBinaryOp::And | AstBinaryOp::Or => None,
     }
 }
+
+/// Whether `op` computes an arbitrary integer, as opposed to the 0/1 of
+/// comparisons and logical operators
+fn is_arithmetic(op: AstBinaryOp) -> bool {
+    matches!(
+        op,
+        AstBinaryOp::Add
+            | AstBinaryOp::Sub
+            | AstBinaryOp::Mul
+            | AstBinaryOp::Div
+            | AstBinaryOp::Mod
+            | AstBinaryOp::BitAnd
+            | AstBinaryOp::BitOr
+            | AstBinaryOp::BitXor
+            | AstBinaryOp::Shl
+            | AstBinaryOp::Shr
+    )
+}
@@ -2,22 +2,107 @@ mod koopa_context;
 mod koopa_generator;
 mod symbol_table;
 mod array_init_helper;
+mod error;
+mod token_dump;
 
 use std::io;
 
 use koopa::ir::Program;
+use lalrpop_util::ParseError;
 
 use koopa::back::KoopaGenerator;
 use koopa_context::KoopaContext;
 use koopa_generator::GenerateKoopa;
 
+pub use error::CompileError;
+pub use token_dump::{describe_expected, emit_tokens, explain_unexpected_token, line_col};
 
-pub fn translate_to_koopa(cu: crate::ast::CompUnit) -> Program {
+
+/// Options controlling Koopa IR generation
+#[derive(Debug, Clone)]
+pub struct FrontendOptions {
+    /// Stop generating after this many errors (0 means no limit)
+    pub max_errors: usize,
+    /// Warn about statements that can never execute
+    pub warn_unreachable: bool,
+    /// Accept `sizeof(a)`, `sizeof(a[0])`, ... (not SysY): the size of a
+    /// variable or of one of its sub-arrays in bytes, not in elements, as a
+    /// constant. Without it, or if the program declares its own `sizeof`,
+    /// `sizeof` is an ordinary identifier
+    pub sizeof: bool,
+}
+
+impl Default for FrontendOptions {
+    fn default() -> Self {
+        Self {
+            max_errors: 20,
+            warn_unreachable: false,
+            sizeof: false,
+        }
+    }
+}
+
+/// Parses SysY source into an AST
+pub fn parse(input: &str) -> Result<crate::ast::CompUnit, CompileError> {
+    let error = match crate::sysy::CompUnitParser::new().parse(input) {
+        Ok(ast) => return Ok(ast),
+        Err(error) => error,
+    };
+    let (location, message) = match error {
+        ParseError::User {
+            error: (location, message),
+        } => (location, message.to_string()),
+        ParseError::UnrecognizedToken {
+            token: (location, token, _),
+            expected,
+        } => match explain_unexpected_token(token.1, &expected) {
+            Some(message) => (location, message),
+            None => (
+                location,
+                format!(
+                    "unexpected '{}', expected {}",
+                    token.1,
+                    describe_expected(&expected)
+                ),
+            ),
+        },
+        ParseError::UnrecognizedEof { location, expected } => (
+            location,
+            format!(
+                "unexpected end of input, expected {}",
+                describe_expected(&expected)
+            ),
+        ),
+        ParseError::InvalidToken { location } => {
+            let c = input[location..].chars().next().unwrap_or_default();
+            (location, format!("invalid character '{}'", c))
+        }
+        ParseError::ExtraToken {
+            token: (location, token, _),
+        } => (location, format!("unexpected '{}'", token.1)),
+    };
+    let (line, col) = line_col(input, location);
+    Err(CompileError::Syntax { line, col, message })
+}
+
+/// Translates the AST into a Koopa IR program.
+/// Generation continues after recoverable errors so that several of them
+/// can be reported at once. All collected errors are returned if there was
+/// any.
+pub fn translate_to_koopa(
+    cu: crate::ast::CompUnit,
+    options: &FrontendOptions,
+) -> Result<Program, Vec<CompileError>> {
     koopa::ir::Type::set_ptr_size(4);
     let mut prog = Program::new();
-    let mut context = KoopaContext::new(&mut prog);
+    let mut context = KoopaContext::new(&mut prog, options.clone());
     cu.generate(&mut context);
-    prog
+    let errors = context.take_errors();
+    if errors.is_empty() {
+        Ok(prog)
+    } else {
+        Err(errors)
+    }
 }
 
 pub fn emit_ir(program: &Program, output: impl io::Write) -> Result<(), std::io::Error> {
@@ -6,12 +6,15 @@ use std::collections::HashMap;
 /// We store their Integer values directly.
 /// For non-constant variables, we store pointers to their allocated memory,
 /// i.e., the Value returned by the `alloc` instruction.
+/// Constant arrays are stored in memory like variables, but their elements
+/// must not be assigned to.
 /// For functions, we store the corresponding handles (`Function`)
 /// Note that a function cannot have the same name as a global variable in SysY
 #[derive(Debug, Copy, Clone)]
 pub enum SymbolInfo {
     ConstVariable(Value),
     Variable(Value),
+    ConstArray(Value),
     Function(Function),
 }
 
@@ -19,6 +22,7 @@ pub enum SymbolInfo {
 /// Outer table is owned by the current table
 /// Top-level table has None as outer
 /// Only the most inner table is owned by KoopaContext
+#[derive(Clone)]
 pub struct SymbolTable {
     level: i32,                         // Scope level for variable shadowing
     table: HashMap<String, SymbolInfo>, // Symbol names DO NOT start with `@` or `%`!
@@ -39,20 +43,34 @@ impl SymbolTable {
         self.level
     }
 
-    pub fn lookup(&self, name: &str) -> SymbolInfo {
-        self.lookup_recursive(name).expect(&format!("Variable {} not found", name))
-    }
-
-    fn lookup_recursive(&self, name: &str) -> Option<SymbolInfo> {
+    /// Looks up `name` in this scope and then in the enclosing ones, or
+    /// returns None if it is not declared
+    pub fn lookup(&self, name: &str) -> Option<SymbolInfo> {
         if let Some(&val) = self.table.get(name) {
             Some(val)
         } else if let Some(outer_table) = &self.outer {
-            outer_table.lookup_recursive(name)
+            outer_table.lookup(name)
         } else {
             None
         }
     }
 
+    /// Whether `name` is declared in this scope (outer scopes are ignored)
+    pub fn contains_local(&self, name: &str) -> bool {
+        self.table.contains_key(name)
+    }
+
+    /// The functions declared in this scope, with their names
+    pub fn functions(&self) -> Vec<(String, Function)> {
+        self.table
+            .iter()
+            .filter_map(|(name, &info)| match info {
+                SymbolInfo::Function(func) => Some((name.clone(), func)),
+                _ => None,
+            })
+            .collect()
+    }
+
     pub fn insert(&mut self, name: String, info: SymbolInfo) {
         self.table.insert(name, info);
     }
@@ -0,0 +1,153 @@
+use lalrpop_util::lexer::MatcherBuilder;
+use lalrpop_util::ParseError;
+use std::io::{self, Write};
+
+// The lexer generated by lalrpop is private to the parser module, so the
+// token dump rebuilds an equivalent one from the same rules.
+// Keep these lists in sync with `sysy.lalrpop`.
+
+/// Patterns that are skipped by the lexer: whitespace and comments
+const SKIPPED: &[&str] = &[
+    r"\s*",
+    r"//[^\n\r]*[\n\r]*",
+    r"/\*[^*]*\*+(?:[^/*][^*]*\*+)*/",
+];
+
+const IDENT_PATTERN: &str = r"[_a-zA-Z][_a-zA-Z0-9]*";
+
+/// (kind, pattern) of tokens described by a regular expression
+const REGEX_TOKENS: &[(&str, &str)] = &[
+    ("ident", IDENT_PATTERN),
+    ("int", r"[1-9][0-9]*"),
+    ("int", r"0[0-7]*"),
+    ("int", r"0[xX][0-9a-fA-F]+"),
+];
+
+const KEYWORDS: &[&str] = &[
+    "int", "void", "const", "return", "if", "else", "while", "do", "for", "break", "continue",
+    "goto", "extern",
+];
+
+const PUNCTUATION: &[&str] = &[
+    "(", ")", "{", "}", "[", "]", ",", ";", ":", "=", "+", "-", "*", "/", "%", "!", "<", ">", "<=",
+    ">=", "==", "!=", "&&", "||", "?", "&", "|", "^", "~", "<<", ">>", "+=", "-=", "*=", "/=",
+    "%=", "&=", "|=", "^=", "<<=", ">>=",
+];
+
+/// Runs only the lexer over `input` and writes one token per line:
+/// its byte span, line:column, kind and text.
+/// Stops at the first character that does not start a valid token.
+pub fn emit_tokens(input: &str, mut output: impl Write) -> io::Result<()> {
+    // Pattern index -> token kind (None for skipped patterns).
+    // On equally long matches the pattern with the highest index wins,
+    // so keywords and punctuation come after the identifier pattern.
+    let mut kinds: Vec<Option<&str>> = Vec::new();
+    let mut patterns: Vec<(String, bool)> = Vec::new();
+    for &pattern in SKIPPED {
+        kinds.push(None);
+        patterns.push((pattern.to_string(), true));
+    }
+    for &(kind, pattern) in REGEX_TOKENS {
+        kinds.push(Some(kind));
+        patterns.push((pattern.to_string(), false));
+    }
+    for &keyword in KEYWORDS {
+        kinds.push(Some("keyword"));
+        patterns.push((escape_regex(keyword), false));
+    }
+    for &punct in PUNCTUATION {
+        kinds.push(Some("punct"));
+        patterns.push((escape_regex(punct), false));
+    }
+
+    let builder = MatcherBuilder::new(patterns).expect("Invalid token pattern");
+    for token in builder.matcher::<()>(input) {
+        match token {
+            Ok((start, token, end)) => {
+                let (line, col) = line_col(input, start);
+                let kind = kinds[token.0].expect("Skipped patterns never produce tokens");
+                writeln!(
+                    output,
+                    "{}..{}\t{}:{}\t{}\t{}",
+                    start, end, line, col, kind, token.1
+                )?;
+            }
+            Err(ParseError::InvalidToken { location }) => {
+                let (line, col) = line_col(input, location);
+                writeln!(output, "error: invalid token at {}:{}", line, col)?;
+                break;
+            }
+            Err(_) => unreachable!("The lexer only reports invalid tokens"),
+        }
+    }
+    Ok(())
+}
+
+/// Explains a parse error at `token`, given the terminals the parser
+/// `expected` there, for the common mistakes that lalrpop reports poorly:
+/// - a declaration as the body of `if`/`else`/`while`, e.g. `if (c) int x;`
+/// - a keyword in place of an identifier, e.g. `int while = 1;`
+pub fn explain_unexpected_token(token: &str, expected: &[String]) -> Option<String> {
+    // lalrpop renders string terminals as `"while"` and regex terminals
+    // as `r#"pattern"#`
+    let expects = |terminal: &str| expected.iter().any(|t| t.trim_matches('"') == terminal);
+    let expects_ident = expected
+        .iter()
+        .any(|terminal| terminal.contains(IDENT_PATTERN));
+
+    // Only a statement, not a declaration, may follow here
+    if matches!(token, "int" | "const") && expects("while") && !expects("int") {
+        Some(format!(
+            "expected statement, found declaration starting with '{}' \
+             (put the declaration in a block: `{{ ... }}`)",
+            token
+        ))
+    } else if expects_ident && KEYWORDS.contains(&token) {
+        Some(format!("expected identifier, found keyword '{}'", token))
+    } else {
+        None
+    }
+}
+
+/// Lists the terminals the parser `expected` for an error message, e.g.
+/// `one of ';', ',', '='`. Regex terminals are named after the token kind
+pub fn describe_expected(expected: &[String]) -> String {
+    let mut names: Vec<String> = Vec::new();
+    for terminal in expected {
+        let name = if terminal.contains(IDENT_PATTERN) {
+            "identifier".to_string()
+        } else if terminal.starts_with("r#") {
+            "integer literal".to_string()
+        } else {
+            format!("'{}'", terminal.trim_matches('"'))
+        };
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    match names.as_slice() {
+        [] => "nothing".to_string(),
+        [name] => name.clone(),
+        _ => format!("one of {}", names.join(", ")),
+    }
+}
+
+/// Converts a byte offset into a 1-based (line, column) pair
+pub fn line_col(input: &str, offset: usize) -> (usize, usize) {
+    let before = &input[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |pos| pos + 1);
+    let col = before[line_start..].chars().count() + 1;
+    (line, col)
+}
+
+fn escape_regex(literal: &str) -> String {
+    let mut escaped = String::new();
+    for c in literal.chars() {
+        if "\\.+*?()|[]{}^$".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
@@ -0,0 +1,406 @@
+use koopa::ir::entities::*;
+use koopa::ir::{values::BinaryOp, *};
+use std::collections::HashMap;
+
+// A tiny interpreter for the Koopa IR emitted by the frontend.
+// It is meant for checking the frontend without a RISC-V toolchain,
+// so it favors simplicity over speed:
+//   - memory is a flat array of 32-bit words, pointers are word indices
+//   - every call pushes a frame mapping IR values to their results, and
+//     gives each alloc of the function one slot for the whole call
+//   - the SysY runtime library is emulated over in-memory input/output
+
+const WORD_SIZE: usize = 4;
+const MAX_CALL_DEPTH: usize = 100_000;
+
+/// Runs `main` of `program` with `input` as stdin.
+/// Returns the exit value of `main` and everything the program printed.
+pub fn run(program: &Program, input: &str) -> Result<(i32, String), String> {
+    let mut interp = Interpreter::new(program, input);
+    let main = program
+        .func_layout()
+        .iter()
+        .copied()
+        .find(|&func| program.func(func).name() == "@main")
+        .ok_or("no 'main' function")?;
+    let exit_value = interp.execute(main)?;
+    Ok((
+        exit_value,
+        String::from_utf8_lossy(&interp.output).into_owned(),
+    ))
+}
+
+struct Interpreter<'a> {
+    program: &'a Program,
+    memory: Vec<i32>,
+    /// Global allocation -> its address in `memory`
+    globals: HashMap<Value, i32>,
+    input: &'a [u8],
+    input_pos: usize,
+    output: Vec<u8>,
+}
+
+/// State of one function invocation.
+/// Calls push frames onto an explicit stack instead of recursing, so deep
+/// SysY recursion does not overflow the interpreter's own stack.
+struct Frame<'a> {
+    func: &'a FunctionData,
+    args: Vec<i32>,
+    /// Results of the instructions executed so far
+    values: HashMap<Value, i32>,
+    bb: BasicBlock,
+    /// Next instruction to execute in `bb`
+    next_inst: Option<Value>,
+    /// The call instruction waiting for the callee to return
+    pending_call: Option<Value>,
+    /// Size of `memory` on entry; allocations are freed on return
+    stack_top: usize,
+}
+
+impl<'a> Frame<'a> {
+    fn new(func: &'a FunctionData, entry_bb: BasicBlock, args: Vec<i32>, stack_top: usize) -> Self {
+        let mut frame = Self {
+            func,
+            args,
+            values: HashMap::new(),
+            bb: entry_bb,
+            next_inst: None,
+            pending_call: None,
+            stack_top,
+        };
+        frame.jump_to(entry_bb);
+        frame
+    }
+
+    fn jump_to(&mut self, bb: BasicBlock) {
+        let node = self
+            .func
+            .layout()
+            .bbs()
+            .node(&bb)
+            .expect("Basic block not in layout");
+        self.bb = bb;
+        self.next_inst = node.insts().front_key().copied();
+    }
+
+    /// Returns the next instruction and advances past it
+    fn fetch(&mut self) -> Result<Value, String> {
+        let inst = self
+            .next_inst
+            .ok_or("basic block does not end with a terminator")?;
+        let node = self
+            .func
+            .layout()
+            .bbs()
+            .node(&self.bb)
+            .expect("Basic block not in layout");
+        self.next_inst = node.insts().cursor(inst).next_key().copied();
+        Ok(inst)
+    }
+}
+
+/// Runtime library functions return right away, functions with a body
+/// need a new frame to be executed
+enum CallOutcome<'a> {
+    Returned(i32),
+    Entered(Frame<'a>),
+}
+
+impl<'a> Interpreter<'a> {
+    fn new(program: &'a Program, input: &'a str) -> Self {
+        let mut interp = Self {
+            program,
+            memory: Vec::new(),
+            globals: HashMap::new(),
+            input: input.as_bytes(),
+            input_pos: 0,
+            output: Vec::new(),
+        };
+        for &global in program.inst_layout() {
+            let address = interp.memory.len() as i32;
+            let ValueKind::GlobalAlloc(alloc) = program.borrow_value(global).kind().clone() else {
+                unreachable!("Expected GlobalAlloc for global variable");
+            };
+            interp.init_global(alloc.init());
+            interp.globals.insert(global, address);
+        }
+        interp
+    }
+
+    /// Appends the words of a global initializer to memory
+    fn init_global(&mut self, init: Value) {
+        let data = self.program.borrow_value(init);
+        match data.kind() {
+            ValueKind::Integer(int) => self.memory.push(int.value()),
+            ValueKind::ZeroInit(_) | ValueKind::Undef(_) => {
+                let words = data.ty().size() / WORD_SIZE;
+                self.memory.resize(self.memory.len() + words, 0);
+            }
+            ValueKind::Aggregate(agg) => {
+                for &elem in agg.elems() {
+                    self.init_global(elem);
+                }
+            }
+            _ => unreachable!("Unsupported global initializer"),
+        }
+    }
+
+    fn call(&mut self, func: Function, args: Vec<i32>) -> Result<CallOutcome<'a>, String> {
+        let func_data = self.program.func(func);
+        match func_data.layout().entry_bb() {
+            Some(entry_bb) => Ok(CallOutcome::Entered(Frame::new(
+                func_data,
+                entry_bb,
+                args,
+                self.memory.len(),
+            ))),
+            None => self
+                .call_runtime(&func_data.name()[1..], &args)
+                .map(CallOutcome::Returned),
+        }
+    }
+
+    fn execute(&mut self, main: Function) -> Result<i32, String> {
+        let mut stack = match self.call(main, Vec::new())? {
+            CallOutcome::Returned(exit_value) => return Ok(exit_value),
+            CallOutcome::Entered(frame) => vec![frame],
+        };
+        loop {
+            let frame = stack.last_mut().expect("Call stack is never empty");
+            let inst = frame.fetch()?;
+            let frame = &*frame;
+            let result = match frame.func.dfg().value(inst).kind() {
+                // An alloc executed again (e.g. in a loop body) reuses its
+                // slot, like the stack frame of the generated code
+                ValueKind::Alloc(_) => match frame.values.get(&inst) {
+                    Some(&address) => address,
+                    None => {
+                        let address = self.memory.len();
+                        let words = self.pointee_size(frame, inst) / WORD_SIZE;
+                        self.memory.resize(address + words, 0);
+                        address as i32
+                    }
+                },
+                ValueKind::Load(load) => {
+                    let address = self.operand(frame, load.src());
+                    self.read(address)?
+                }
+                ValueKind::Store(store) => {
+                    let value = self.operand(frame, store.value());
+                    let address = self.operand(frame, store.dest());
+                    self.write(address, value)?;
+                    continue;
+                }
+                ValueKind::GetPtr(get_ptr) => {
+                    // *T -> *T, step over whole T's
+                    let base = self.operand(frame, get_ptr.src());
+                    let index = self.operand(frame, get_ptr.index());
+                    let step = self.pointee_size(frame, get_ptr.src()) / WORD_SIZE;
+                    base.wrapping_add(index.wrapping_mul(step as i32))
+                }
+                ValueKind::GetElemPtr(get_elem_ptr) => {
+                    // *[T, n] -> *T, step over T's
+                    let base = self.operand(frame, get_elem_ptr.src());
+                    let index = self.operand(frame, get_elem_ptr.index());
+                    let step = self.pointee_size(frame, inst) / WORD_SIZE;
+                    base.wrapping_add(index.wrapping_mul(step as i32))
+                }
+                ValueKind::Binary(binary) => {
+                    let lhs = self.operand(frame, binary.lhs());
+                    let rhs = self.operand(frame, binary.rhs());
+                    eval_binary(binary.op(), lhs, rhs).ok_or("division by zero")?
+                }
+                ValueKind::Call(call) => {
+                    let args = call
+                        .args()
+                        .iter()
+                        .map(|&arg| self.operand(frame, arg))
+                        .collect();
+                    match self.call(call.callee(), args)? {
+                        CallOutcome::Returned(result) => result,
+                        CallOutcome::Entered(callee) => {
+                            if stack.len() == MAX_CALL_DEPTH {
+                                return Err(format!(
+                                    "call depth limit ({}) exceeded",
+                                    MAX_CALL_DEPTH
+                                ));
+                            }
+                            stack.last_mut().unwrap().pending_call = Some(inst);
+                            stack.push(callee);
+                            continue;
+                        }
+                    }
+                }
+                ValueKind::Branch(branch) => {
+                    let target = if self.operand(frame, branch.cond()) != 0 {
+                        branch.true_bb()
+                    } else {
+                        branch.false_bb()
+                    };
+                    stack.last_mut().unwrap().jump_to(target);
+                    continue;
+                }
+                ValueKind::Jump(jump) => {
+                    let target = jump.target();
+                    stack.last_mut().unwrap().jump_to(target);
+                    continue;
+                }
+                ValueKind::Return(ret) => {
+                    let value = ret.value().map_or(0, |value| self.operand(frame, value));
+                    let callee = stack.pop().unwrap();
+                    self.memory.truncate(callee.stack_top);
+                    let Some(caller) = stack.last_mut() else {
+                        return Ok(value);
+                    };
+                    let call = caller
+                        .pending_call
+                        .take()
+                        .expect("Caller is waiting for a call");
+                    caller.values.insert(call, value);
+                    continue;
+                }
+                kind => unreachable!("Unexpected instruction: {:?}", kind),
+            };
+            stack.last_mut().unwrap().values.insert(inst, result);
+        }
+    }
+
+    /// Evaluates an instruction operand in the current frame
+    fn operand(&self, frame: &Frame, value: Value) -> i32 {
+        if let Some(&address) = self.globals.get(&value) {
+            return address;
+        }
+        match frame.func.dfg().value(value).kind() {
+            ValueKind::Integer(int) => int.value(),
+            ValueKind::ZeroInit(_) | ValueKind::Undef(_) => 0,
+            ValueKind::FuncArgRef(arg) => frame.args[arg.index()],
+            _ => *frame
+                .values
+                .get(&value)
+                .expect("Operand used before it is defined"),
+        }
+    }
+
+    /// Byte size of the type a pointer value points to
+    fn pointee_size(&self, frame: &Frame, ptr: Value) -> usize {
+        let ty = if self.globals.contains_key(&ptr) {
+            self.program.borrow_value(ptr).ty().clone()
+        } else {
+            frame.func.dfg().value(ptr).ty().clone()
+        };
+        let TypeKind::Pointer(base) = ty.kind() else {
+            unreachable!("Expected a pointer type, found {}", ty);
+        };
+        base.size()
+    }
+
+    fn read(&self, address: i32) -> Result<i32, String> {
+        usize::try_from(address)
+            .ok()
+            .and_then(|address| self.memory.get(address).copied())
+            .ok_or_else(|| format!("out-of-bounds load from address {}", address))
+    }
+
+    fn write(&mut self, address: i32, value: i32) -> Result<(), String> {
+        let slot = usize::try_from(address)
+            .ok()
+            .and_then(|address| self.memory.get_mut(address))
+            .ok_or_else(|| format!("out-of-bounds store to address {}", address))?;
+        *slot = value;
+        Ok(())
+    }
+
+    /// Emulates the SysY runtime library
+    fn call_runtime(&mut self, name: &str, args: &[i32]) -> Result<i32, String> {
+        match name {
+            "getint" => self.read_int(),
+            "getch" => Ok(self.read_byte().map_or(-1, i32::from)),
+            "getarray" => {
+                let len = self.read_int()?;
+                for i in 0..len {
+                    let value = self.read_int()?;
+                    self.write(args[0].wrapping_add(i), value)?;
+                }
+                Ok(len)
+            }
+            "putint" => {
+                self.output.extend(args[0].to_string().bytes());
+                Ok(0)
+            }
+            "putch" => {
+                self.output.push(args[0] as u8);
+                Ok(0)
+            }
+            "putarray" => {
+                self.output.extend(format!("{}:", args[0]).bytes());
+                for i in 0..args[0] {
+                    let value = self.read(args[1].wrapping_add(i))?;
+                    self.output.extend(format!(" {}", value).bytes());
+                }
+                self.output.push(b'\n');
+                Ok(0)
+            }
+            "starttime" | "stoptime" => Ok(0),
+            _ => Err(format!("call to undefined function '{}'", name)),
+        }
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        let byte = self.input.get(self.input_pos).copied()?;
+        self.input_pos += 1;
+        Some(byte)
+    }
+
+    fn read_int(&mut self) -> Result<i32, String> {
+        while self
+            .input
+            .get(self.input_pos)
+            .is_some_and(|byte| byte.is_ascii_whitespace())
+        {
+            self.input_pos += 1;
+        }
+        let start = self.input_pos;
+        if self.input.get(self.input_pos) == Some(&b'-') {
+            self.input_pos += 1;
+        }
+        while self
+            .input
+            .get(self.input_pos)
+            .is_some_and(|byte| byte.is_ascii_digit())
+        {
+            self.input_pos += 1;
+        }
+        std::str::from_utf8(&self.input[start..self.input_pos])
+            .ok()
+            .and_then(|text| text.parse().ok())
+            .ok_or_else(|| "getint: expected an integer in the input".to_string())
+    }
+}
+
+/// Computes `lhs op rhs` like the generated code, or None for a division
+/// or remainder by zero. Constant folding (`opt::fold`) uses the same
+/// arithmetic
+pub(crate) fn eval_binary(op: BinaryOp, lhs: i32, rhs: i32) -> Option<i32> {
+    let value = match op {
+        BinaryOp::NotEq => (lhs != rhs) as i32,
+        BinaryOp::Eq => (lhs == rhs) as i32,
+        BinaryOp::Gt => (lhs > rhs) as i32,
+        BinaryOp::Lt => (lhs < rhs) as i32,
+        BinaryOp::Ge => (lhs >= rhs) as i32,
+        BinaryOp::Le => (lhs <= rhs) as i32,
+        BinaryOp::Add => lhs.wrapping_add(rhs),
+        BinaryOp::Sub => lhs.wrapping_sub(rhs),
+        BinaryOp::Mul => lhs.wrapping_mul(rhs),
+        BinaryOp::Div | BinaryOp::Mod if rhs == 0 => return None,
+        BinaryOp::Div => lhs.wrapping_div(rhs),
+        BinaryOp::Mod => lhs.wrapping_rem(rhs),
+        BinaryOp::And => lhs & rhs,
+        BinaryOp::Or => lhs | rhs,
+        BinaryOp::Xor => lhs ^ rhs,
+        // Shift amounts are taken modulo 32, like `sll`/`srl`/`sra`
+        BinaryOp::Shl => lhs.wrapping_shl(rhs as u32),
+        BinaryOp::Shr => ((lhs as u32).wrapping_shr(rhs as u32)) as i32,
+        BinaryOp::Sar => lhs.wrapping_shr(rhs as u32),
+    };
+    Some(value)
+}
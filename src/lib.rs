@@ -0,0 +1,44 @@
+use lalrpop_util::lalrpop_mod;
+
+pub mod ast;
+pub mod backend;
+pub mod cfg;
+pub mod frontend;
+pub mod interp;
+pub mod opt;
+pub mod stats;
+pub mod verify;
+
+lalrpop_mod!(#[allow(clippy::all)] sysy);
+
+pub use frontend::CompileError;
+
+/// Compiles SysY source to Koopa IR text, like `-koopa` without options.
+/// Only the first error is returned
+pub fn compile_to_koopa(src: &str) -> Result<String, CompileError> {
+    let program = translate(src)?;
+    let mut koopa = Vec::new();
+    frontend::emit_ir(&program, &mut koopa).map_err(backend_error)?;
+    Ok(String::from_utf8_lossy(&koopa).into_owned())
+}
+
+/// Compiles SysY source to RISC-V assembly, like `-riscv` without options.
+/// Only the first error is returned
+pub fn compile_to_riscv(src: &str) -> Result<String, CompileError> {
+    let program = translate(src)?;
+    let mut asm = Vec::new();
+    let options = backend::BackendOptions::default();
+    let mut stats = stats::Stats::default();
+    backend::emit_riscv(&program, &mut asm, &options, &mut stats).map_err(backend_error)?;
+    Ok(String::from_utf8_lossy(&asm).into_owned())
+}
+
+fn translate(src: &str) -> Result<koopa::ir::Program, CompileError> {
+    let ast = frontend::parse(src)?;
+    frontend::translate_to_koopa(ast, &frontend::FrontendOptions::default())
+        .map_err(|mut errors| errors.swap_remove(0))
+}
+
+fn backend_error(error: std::io::Error) -> CompileError {
+    CompileError::Backend(error.to_string())
+}
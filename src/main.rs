@@ -1,54 +1,369 @@
-use lalrpop_util::lalrpop_mod;
+use compiler::{ast, backend, cfg, frontend, interp, opt, stats, verify};
 use std::env::args;
 use std::fs::read_to_string;
-use std::io::Result;
+use std::io::{Result, Write};
 
-pub mod ast;
-pub mod frontend;
-pub mod backend;
+struct Cmdline {
+    mode: String,
+    input: String,          // `-` for stdin
+    output: Option<String>, // None only for `-check`, which emits nothing
+    frontend_options: frontend::FrontendOptions,
+    backend_options: backend::BackendOptions,
+    stats: bool,                      // Print a size summary to stderr after compiling
+    opt_level: Option<opt::OptLevel>, // None: the default of the mode
+}
+
+const MODES: &[&str] = &[
+    "-koopa",
+    "-riscv",
+    "-perf",
+    "-check",
+    "-interp",
+    "-compare",
+    "-emit-tokens",
+    "-ast",
+    "-ast-json",
+    "-cfg",
+];
+
+const USAGE: &str = "\
+usage: compiler <mode> <input> -o <output> [options]
+modes: -koopa, -riscv, -perf, -check, -interp, -compare, -emit-tokens, -ast,
+       -ast-json (needs the `serde` feature), -cfg
+<input> may be `-` to read the source from stdin; `-o <output>` may come
+anywhere after the mode and is optional with -check";
+
+// Cmdline example: sysyrc <mode> <input> -o <output> [options]
+// `-O0`/`-O1`/`-O2` (or `--optimize-level N`) select the IR passes
+// independently of the mode; see `opt::OptLevel`
+// `-check` only reports diagnostics, so `-o <output>` is optional there
+fn parse_cmdline() -> std::result::Result<Cmdline, String> {
+    let mut args = args().skip(1);
+    let mode = args.next().ok_or("missing mode")?;
+    if !MODES.contains(&mode.as_str()) {
+        return Err(format!("unknown mode: {}", mode));
+    }
+
+    let mut input = None;
+    let mut output = None;
+    let mut frontend_options = frontend::FrontendOptions::default();
+    let mut backend_options = backend::BackendOptions::default();
+    let mut stats = false;
+    let mut opt_level = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => {
+                let path = option_value(&mut args, "-o", "an output file")?;
+                if output.replace(path).is_some() {
+                    return Err("more than one output file".to_string());
+                }
+            }
+            "--max-errors" => {
+                let limit = option_value(&mut args, "--max-errors", "a number")?;
+                frontend_options.max_errors = limit
+                    .parse()
+                    .map_err(|_| format!("invalid error limit: {}", limit))?;
+            }
+            "--max-frame-size" => {
+                let limit = option_value(&mut args, "--max-frame-size", "a number")?;
+                backend_options.max_frame_size = Some(
+                    limit
+                        .parse()
+                        .map_err(|_| format!("invalid frame size limit: {}", limit))?,
+                );
+            }
+            "--frame-size-error" => backend_options.frame_size_error = true,
+            "--target-abi" => {
+                let abi = option_value(&mut args, "--target-abi", "an ABI name")?;
+                backend_options.target_abi =
+                    backend::TargetAbi::from_name(&abi).ok_or_else(|| {
+                        format!("unsupported target ABI: {} (expected ilp32 or ilp32f)", abi)
+                    })?;
+            }
+            "-Wunreachable" => frontend_options.warn_unreachable = true,
+            "--sizeof" => frontend_options.sizeof = true,
+            "--dump-frames" => backend_options.dump_frames = true,
+            "--emit-clobbers" => backend_options.emit_clobbers = true,
+            "--frame-pointer" => backend_options.frame_pointer = true,
+            "--no-frame-rounding" => backend_options.dump_unrounded_frames = true,
+            "--data-layout" => backend_options.dump_data_layout = true,
+            "--comments" => backend_options.comments = true,
+            "--local-functions" => backend_options.local_functions = true,
+            "--no-m-ext" => backend_options.no_m_ext = true,
+            "--numeric-regs" => backend_options.numeric_regs = true,
+            "--stats" => stats = true,
+            "--optimize-level" => {
+                let level = option_value(&mut args, "--optimize-level", "a level")?;
+                opt_level = Some(parse_opt_level(&level)?);
+            }
+            _ if arg.starts_with("-O") => opt_level = Some(parse_opt_level(&arg[2..])?),
+            // `-` alone is stdin, anything else starting with `-` an option
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(format!("unknown option: {}", arg));
+            }
+            _ => {
+                if input.replace(arg).is_some() {
+                    return Err("more than one input file".to_string());
+                }
+            }
+        }
+    }
 
-lalrpop_mod!(sysy);  
+    let input = input.ok_or("missing input file")?;
+    if output.is_none() && mode != "-check" {
+        return Err(format!("{} requires an output file (-o <output>)", mode));
+    }
+    // The interpreted program reads its own input from stdin
+    if input == "-" && mode == "-interp" {
+        return Err("-interp cannot read the source from stdin".to_string());
+    }
+    Ok(Cmdline {
+        mode,
+        input,
+        output,
+        frontend_options,
+        backend_options,
+        stats,
+        opt_level,
+    })
+}
 
-// Cmdline example: sysyrc <mode> <input> -o <output>
-fn parse_cmdline() -> (String, String, String) {
-    let mut args = args();
-    args.next();
-    let mode = args.next().unwrap();
-    let input = args.next().unwrap();
-    args.next();
-    let output = args.next().unwrap();
-    (mode, input, output)
+/// Returns the argument following `option`, which must be `what`
+fn option_value(
+    args: &mut impl Iterator<Item = String>,
+    option: &str,
+    what: &str,
+) -> std::result::Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("{} requires {}", option, what))
+}
+
+fn parse_opt_level(level: &str) -> std::result::Result<opt::OptLevel, String> {
+    opt::OptLevel::from_name(level)
+        .ok_or_else(|| format!("invalid optimization level: {} (expected 0, 1 or 2)", level))
 }
 
 fn main() -> Result<()> {
-    let (mode, input, output) = parse_cmdline();
+    let Cmdline {
+        mode,
+        input,
+        output,
+        frontend_options,
+        backend_options,
+        stats: print_stats,
+        opt_level,
+    } = match parse_cmdline() {
+        Ok(cmdline) => cmdline,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            eprintln!("{}", USAGE);
+            std::process::exit(1);
+        }
+    };
+
+    let mut writer: Box<dyn Write> = match &output {
+        Some(path) => Box::new(std::io::BufWriter::new(std::fs::File::create(path)?)),
+        None => Box::new(std::io::sink()),
+    };
+
+    let input: String = if input == "-" {
+        std::io::read_to_string(std::io::stdin())?
+    } else {
+        read_to_string(input)?
+    };
 
-    let output = std::fs::File::create(output)?;
-    let writer = std::io::BufWriter::new(output);
+    // Lexer debugging: dump the tokens instead of compiling
+    if mode == "-emit-tokens" {
+        return frontend::emit_tokens(&input, writer);
+    }
 
-    let input: String = read_to_string(input)?;
+    let ast = match frontend::parse(&input) {
+        Ok(ast) => ast,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            std::process::exit(1);
+        }
+    };
 
-    let parser = sysy::CompUnitParser::new();
+    // Parser debugging: dump the AST instead of compiling
+    if mode == "-ast" {
+        return write!(writer, "{}", ast::pretty_print(&ast));
+    }
+    if mode == "-ast-json" {
+        return emit_ast_json(&ast, writer);
+    }
 
-    let Ok(ast) = parser.parse(&input) else {
-        panic!("Failed to parse input"); 
+    let mut koopa_ir = match frontend::translate_to_koopa(ast, &frontend_options) {
+        Ok(program) => program,
+        Err(errors) => {
+            for error in &errors {
+                eprintln!("error: {}", error);
+            }
+            eprintln!("{} error(s) generated", errors.len());
+            std::process::exit(1);
+        }
     };
+    let mut stats = stats::Stats::default();
+    stats.record_frontend(&koopa_ir);
 
-    let koopa_ir = frontend::translate_to_koopa(ast);
+    // `-perf` defaults to the full pipeline, the other modes to none.
+    // `-perf` and `-compare` optimize as part of their own pipeline
+    let perf_pipeline = matches!(mode.as_str(), "-perf" | "-compare");
+    let default_level = if perf_pipeline {
+        opt::OptLevel::O2
+    } else {
+        opt::OptLevel::O0
+    };
+    let opt_level = opt_level.unwrap_or(default_level);
+    if !perf_pipeline {
+        opt::optimize(&mut koopa_ir, opt_level, &mut stats);
+    }
 
+    // The backend assumes well-formed IR and would panic on anything else
+    if matches!(mode.as_str(), "-riscv" | "-perf" | "-compare") {
+        verify_ir(&koopa_ir);
+    }
 
     match mode.as_str() {
+        // The program is valid: nothing to emit
+        "-check" => {}
         "-koopa" => {
             frontend::emit_ir(&koopa_ir, writer)?;
         }
+        // Control-flow graph of the IR, in Graphviz DOT format
+        "-cfg" => {
+            cfg::emit_dot(&koopa_ir, writer)?;
+        }
         "-riscv" => {
-            backend::emit_riscv(&koopa_ir, writer)?;
+            backend::emit_riscv(&koopa_ir, writer, &backend_options, &mut stats)?;
         }
         "-perf" => {
-            backend::emit_riscv(&koopa_ir, writer)?;
+            emit_perf(
+                &mut koopa_ir,
+                writer,
+                &backend_options,
+                opt_level,
+                &mut stats,
+            )?;
         }
-        _ => panic!("Unknown mode: {}", mode),
+        "-interp" => {
+            // Same format as the reference outputs of the SysY test suite:
+            // the program's output, then the exit code on its own line
+            let stdin = std::io::read_to_string(std::io::stdin())?;
+            match interp::run(&koopa_ir, &stdin) {
+                Ok((exit_value, program_output)) => {
+                    write!(writer, "{}", program_output)?;
+                    if !program_output.is_empty() && !program_output.ends_with('\n') {
+                        writeln!(writer)?;
+                    }
+                    writeln!(writer, "{}", exit_value & 0xff)?;
+                }
+                Err(error) => {
+                    eprintln!("error: {}", error);
+                    std::process::exit(1);
+                }
+            }
+        }
+        "-compare" => {
+            let output = output.expect("-compare requires an output file");
+            compare_backends(
+                &mut koopa_ir,
+                &output,
+                &mut writer,
+                &backend_options,
+                opt_level,
+                &mut stats,
+            )?;
+        }
+        _ => unreachable!("parse_cmdline only accepts known modes"),
     };
 
+    if print_stats {
+        eprint!("{}", stats);
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Writes the AST as JSON. Enum values are objects whose `kind` names the
+/// variant and whose `value` holds its contents, if any
+#[cfg(feature = "serde")]
+fn emit_ast_json(ast: &ast::CompUnit, mut writer: impl Write) -> Result<()> {
+    serde_json::to_writer_pretty(&mut writer, ast)?;
+    writeln!(writer)
+}
+
+#[cfg(not(feature = "serde"))]
+fn emit_ast_json(_ast: &ast::CompUnit, _writer: impl Write) -> Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "-ast-json requires building with the `serde` feature",
+    ))
+}
+
+/// Exits with a diagnostic for every malformed construct of `program`,
+/// which is a bug in the compiler rather than in the source
+fn verify_ir(program: &koopa::ir::Program) {
+    if let Err(errors) = verify::verify(program) {
+        for error in &errors {
+            eprintln!("error: invalid Koopa IR: {}", error);
+        }
+        eprintln!("{} error(s) generated", errors.len());
+        std::process::exit(1);
+    }
+}
+
+/// The `-perf` pipeline: optimizes the IR in place at `opt_level`, then
+/// emits assembly with the backend optimizations enabled
+fn emit_perf(
+    koopa_ir: &mut koopa::ir::Program,
+    writer: impl Write,
+    backend_options: &backend::BackendOptions,
+    opt_level: opt::OptLevel,
+    stats: &mut stats::Stats,
+) -> Result<()> {
+    opt::optimize(koopa_ir, opt_level, stats);
+    verify_ir(koopa_ir);
+    let backend_options = backend::BackendOptions {
+        reuse_constants: true,
+        allocate_registers: true,
+        strength_reduction: true,
+        peephole: true,
+        ..backend_options.clone()
+    };
+    backend::emit_riscv(koopa_ir, writer, &backend_options, stats)
+}
+
+/// Emits the naive (`-riscv`) and optimized (`-perf`) assembly of the same
+/// program to `<output>.riscv` and `<output>.perf` for manual diffing, and
+/// writes a summary of both to `writer`.
+/// `--stats` reports the optimized version
+fn compare_backends(
+    koopa_ir: &mut koopa::ir::Program,
+    output: &str,
+    mut writer: impl Write,
+    backend_options: &backend::BackendOptions,
+    opt_level: opt::OptLevel,
+    stats: &mut stats::Stats,
+) -> Result<()> {
+    let mut naive = Vec::new();
+    backend::emit_riscv(koopa_ir, &mut naive, backend_options, stats)?;
+    let mut optimized = Vec::new();
+    emit_perf(koopa_ir, &mut optimized, backend_options, opt_level, stats)?;
+
+    for (suffix, asm) in [("riscv", &naive), ("perf", &optimized)] {
+        let path = format!("{}.{}", output, suffix);
+        std::fs::write(&path, asm)?;
+        writeln!(writer, "{}: {} instructions", path, count_instructions(asm))?;
+    }
+    Ok(())
+}
+
+/// Counts the instruction lines of an assembly listing, skipping
+/// directives, comments and labels
+fn count_instructions(asm: &[u8]) -> usize {
+    String::from_utf8_lossy(asm)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with(['.', '#']) && !line.ends_with(':'))
+        .count()
+}
@@ -0,0 +1,60 @@
+use super::replace_all_uses;
+use koopa::ir::builder_traits::*;
+use koopa::ir::{BasicBlock, BinaryOp, Function, FunctionData, Value, ValueKind};
+use koopa::opt::FunctionPass;
+
+/// Simplifies binary instructions with a constant operand using algebraic
+/// identities: `x * 0 -> 0`, `x + 0`, `x - 0`, `x * 1`, `x / 1 -> x`.
+///
+/// Operands are separate instructions in the IR, so dropping one never
+/// drops a call: `f() * 0` still calls `f`, only the multiply goes away.
+pub struct AlgebraicSimplify;
+
+/// What a simplified instruction evaluates to
+enum Simplified {
+    Operand(Value),
+    Integer(i32),
+}
+
+impl FunctionPass for AlgebraicSimplify {
+    fn run_on(&mut self, _func: Function, data: &mut FunctionData) {
+        let insts: Vec<(BasicBlock, Value)> = data
+            .layout()
+            .bbs()
+            .iter()
+            .flat_map(|(&bb, node)| node.insts().keys().map(move |&inst| (bb, inst)))
+            .collect();
+        for (bb, inst) in insts {
+            let Some(simplified) = simplify(data, inst) else {
+                continue;
+            };
+            let replacement = match simplified {
+                Simplified::Operand(value) => value,
+                Simplified::Integer(value) => data.dfg_mut().new_value().integer(value),
+            };
+            replace_all_uses(data, inst, replacement);
+            data.layout_mut().bb_mut(bb).insts_mut().remove(&inst);
+            data.dfg_mut().remove_value(inst);
+        }
+    }
+}
+
+fn simplify(data: &FunctionData, inst: Value) -> Option<Simplified> {
+    let ValueKind::Binary(binary) = data.dfg().value(inst).kind() else {
+        return None;
+    };
+    let constant = |value: Value| match data.dfg().value(value).kind() {
+        ValueKind::Integer(int) => Some(int.value()),
+        _ => None,
+    };
+    let (lhs, rhs) = (binary.lhs(), binary.rhs());
+    match (binary.op(), constant(lhs), constant(rhs)) {
+        (BinaryOp::Mul, _, Some(0)) | (BinaryOp::Mul, Some(0), _) => Some(Simplified::Integer(0)),
+        (BinaryOp::Add | BinaryOp::Sub, _, Some(0))
+        | (BinaryOp::Mul | BinaryOp::Div, _, Some(1)) => Some(Simplified::Operand(lhs)),
+        (BinaryOp::Add, Some(0), _) | (BinaryOp::Mul, Some(1), _) => {
+            Some(Simplified::Operand(rhs))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,45 @@
+use koopa::ir::{BasicBlock, Function, FunctionData, Value, ValueKind};
+use koopa::opt::FunctionPass;
+
+/// Removes the instructions whose result is never used and that have no
+/// side effect: `alloc`, `load`, `getptr`, `getelemptr` and binary
+/// operations (a division by zero does not trap on RISC-V).
+///
+/// `store`, `call`, `ret`, `br` and `jump` are always kept.
+pub struct DeadCodeElimination;
+
+impl FunctionPass for DeadCodeElimination {
+    fn run_on(&mut self, _func: Function, data: &mut FunctionData) {
+        // Removing an instruction can leave its operands unused, so repeat
+        // until nothing changes
+        loop {
+            let dead: Vec<(BasicBlock, Value)> = data
+                .layout()
+                .bbs()
+                .iter()
+                .flat_map(|(&bb, node)| node.insts().keys().map(move |&inst| (bb, inst)))
+                .filter(|&(_, inst)| is_dead(data, inst))
+                .collect();
+            if dead.is_empty() {
+                break;
+            }
+            for (bb, inst) in dead {
+                data.layout_mut().bb_mut(bb).insts_mut().remove(&inst);
+                data.dfg_mut().remove_value(inst);
+            }
+        }
+    }
+}
+
+fn is_dead(data: &FunctionData, inst: Value) -> bool {
+    let value = data.dfg().value(inst);
+    let pure = matches!(
+        value.kind(),
+        ValueKind::Alloc(_)
+            | ValueKind::Load(_)
+            | ValueKind::GetPtr(_)
+            | ValueKind::GetElemPtr(_)
+            | ValueKind::Binary(_)
+    );
+    pure && value.used_by().is_empty()
+}
@@ -0,0 +1,60 @@
+use super::replace_all_uses;
+use crate::interp::eval_binary;
+use koopa::ir::builder_traits::*;
+use koopa::ir::{BasicBlock, Function, FunctionData, Value, ValueKind};
+use koopa::opt::FunctionPass;
+
+/// Replaces binary instructions whose operands are both constants with the
+/// constant they compute, e.g. `add 2, 3 -> 5`.
+///
+/// The arithmetic is the one of the interpreter (`interp::eval_binary`): it
+/// wraps around like the generated code. A division or remainder by zero is left alone,
+/// so that it still happens at run time.
+pub struct ConstantFold;
+
+impl FunctionPass for ConstantFold {
+    fn run_on(&mut self, _func: Function, data: &mut FunctionData) {
+        // Folding an instruction can make its users foldable. They usually
+        // come later in the layout, but not always (e.g. loops), so repeat
+        // until nothing changes
+        loop {
+            let insts: Vec<(BasicBlock, Value)> = data
+                .layout()
+                .bbs()
+                .iter()
+                .flat_map(|(&bb, node)| node.insts().keys().map(move |&inst| (bb, inst)))
+                .collect();
+            let mut changed = false;
+            for (bb, inst) in insts {
+                let Some(folded) = fold(data, inst) else {
+                    continue;
+                };
+                let folded = data.dfg_mut().new_value().integer(folded);
+                replace_all_uses(data, inst, folded);
+                data.layout_mut().bb_mut(bb).insts_mut().remove(&inst);
+                data.dfg_mut().remove_value(inst);
+                changed = true;
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+}
+
+fn fold(data: &FunctionData, inst: Value) -> Option<i32> {
+    let ValueKind::Binary(binary) = data.dfg().value(inst).kind() else {
+        return None;
+    };
+    let constant = |value: Value| {
+        if value.is_global() {
+            return None;
+        }
+        match data.dfg().value(value).kind() {
+            ValueKind::Integer(int) => Some(int.value()),
+            _ => None,
+        }
+    };
+    let (lhs, rhs) = (constant(binary.lhs())?, constant(binary.rhs())?);
+    eval_binary(binary.op(), lhs, rhs)
+}
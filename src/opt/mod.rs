@@ -0,0 +1,115 @@
+mod algebraic;
+mod dce;
+mod fold;
+mod reassociate;
+
+use crate::stats::{count_koopa_insts, Stats};
+use algebraic::AlgebraicSimplify;
+use dce::DeadCodeElimination;
+use fold::ConstantFold;
+use koopa::ir::builder_traits::*;
+use koopa::ir::{FunctionData, Program, Value, ValueKind};
+use koopa::opt::{FunctionPass, Pass, PassManager};
+use reassociate::Reassociate;
+
+/// Which IR passes run, selected by `-O0`/`-O1`/`-O2`.
+///
+/// - `O0`: no passes
+/// - `O1`: `fold-constants`, `algebraic-simplify`, then `dead-code`
+/// - `O2`: `fold-constants`, `reassociate`, `algebraic-simplify`, then
+///   `dead-code`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OptLevel {
+    O0,
+    O1,
+    O2,
+}
+
+impl OptLevel {
+    /// Parses a level as accepted by `-O<level>` and `--optimize-level`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "0" => Some(OptLevel::O0),
+            "1" => Some(OptLevel::O1),
+            "2" => Some(OptLevel::O2),
+            _ => None,
+        }
+    }
+
+    /// The passes of this level, in the order they run
+    fn passes(self) -> Vec<(&'static str, Box<dyn FunctionPass>)> {
+        // Reassociation may leave `x + 0` or `x * 1` behind for the
+        // algebraic simplification
+        let mut passes: Vec<(&'static str, Box<dyn FunctionPass>)> = Vec::new();
+        if self >= OptLevel::O1 {
+            passes.push(("fold-constants", Box::new(ConstantFold)));
+        }
+        if self >= OptLevel::O2 {
+            passes.push(("reassociate", Box::new(Reassociate)));
+        }
+        if self >= OptLevel::O1 {
+            passes.push(("algebraic-simplify", Box::new(AlgebraicSimplify)));
+            passes.push(("dead-code", Box::new(DeadCodeElimination)));
+        }
+        passes
+    }
+}
+
+/// Runs the IR passes of `level` on `program` in place, recording how many
+/// instructions each pass removed in `stats`
+pub fn optimize(program: &mut Program, level: OptLevel, stats: &mut Stats) {
+    for (name, pass) in level.passes() {
+        let before = count_koopa_insts(program);
+        let mut passman = PassManager::new();
+        passman.register(Pass::Function(pass));
+        passman.run_passes(program);
+        let removed = before.saturating_sub(count_koopa_insts(program));
+        stats.removed_by_pass.push((name, removed));
+    }
+}
+
+/// Rewrites every instruction that uses `old` to use `new` instead
+fn replace_all_uses(func: &mut FunctionData, old: Value, new: Value) {
+    let users: Vec<Value> = func.dfg().value(old).used_by().iter().copied().collect();
+    for user in users {
+        let mut data = func.dfg().value(user).clone();
+        let substitute = |operand: &mut Value| {
+            if *operand == old {
+                *operand = new;
+            }
+        };
+        match data.kind_mut() {
+            ValueKind::Load(load) => substitute(load.src_mut()),
+            ValueKind::Store(store) => {
+                substitute(store.value_mut());
+                substitute(store.dest_mut());
+            }
+            ValueKind::GetPtr(get_ptr) => {
+                substitute(get_ptr.src_mut());
+                substitute(get_ptr.index_mut());
+            }
+            ValueKind::GetElemPtr(get_elem_ptr) => {
+                substitute(get_elem_ptr.src_mut());
+                substitute(get_elem_ptr.index_mut());
+            }
+            ValueKind::Binary(binary) => {
+                substitute(binary.lhs_mut());
+                substitute(binary.rhs_mut());
+            }
+            ValueKind::Branch(branch) => {
+                substitute(branch.cond_mut());
+                branch.true_args_mut().iter_mut().for_each(substitute);
+                branch.false_args_mut().iter_mut().for_each(substitute);
+            }
+            ValueKind::Jump(jump) => jump.args_mut().iter_mut().for_each(substitute),
+            ValueKind::Call(call) => call.args_mut().iter_mut().for_each(substitute),
+            ValueKind::Return(ret) => {
+                if let Some(value) = ret.value_mut() {
+                    substitute(value);
+                }
+            }
+            _ => unreachable!("Unexpected user of an instruction: {:?}", data.kind()),
+        }
+        func.dfg_mut().replace_value_with(user).raw(data);
+    }
+}
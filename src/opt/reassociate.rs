@@ -0,0 +1,87 @@
+use koopa::ir::builder_traits::*;
+use koopa::ir::{BinaryOp, Function, FunctionData, Value, ValueKind};
+use koopa::opt::FunctionPass;
+
+/// Folds the constants of `+` and `*` chains with one variable operand:
+/// `(x + 2) + 3 -> x + 5` and `(x * 2) * 4 -> x * 8`, in either operand
+/// order.
+///
+/// Both operations are associative and commutative in wrapping 32-bit
+/// arithmetic, which is what the generated code computes, so the constants
+/// are combined with wrapping arithmetic too. Chains mixing operators
+/// (`x - 2 + 3`, `x * 2 + 3`) are left alone.
+pub struct Reassociate;
+
+impl FunctionPass for Reassociate {
+    fn run_on(&mut self, _func: Function, data: &mut FunctionData) {
+        // Instructions are visited in layout order, so a longer chain is
+        // folded one link at a time
+        let insts: Vec<Value> = data
+            .layout()
+            .bbs()
+            .nodes()
+            .flat_map(|node| node.insts().keys().copied())
+            .collect();
+        for inst in insts {
+            let Some((op, var, inner, constant)) = reassociate(data, inst) else {
+                continue;
+            };
+            let constant = data.dfg_mut().new_value().integer(constant);
+            data.dfg_mut()
+                .replace_value_with(inst)
+                .binary(op, var, constant);
+
+            if data.dfg().value(inner).used_by().is_empty() {
+                let bb = data.layout().parent_bb(inner).unwrap();
+                data.layout_mut().bb_mut(bb).insts_mut().remove(&inner);
+                data.dfg_mut().remove_value(inner);
+            }
+        }
+    }
+}
+
+/// Matches `inst` against `(var op c1) op c2` and returns the operator,
+/// `var`, the inner instruction and the combined constant
+fn reassociate(data: &FunctionData, inst: Value) -> Option<(BinaryOp, Value, Value, i32)> {
+    let (op, lhs, rhs) = binary(data, inst)?;
+    let combine = match op {
+        BinaryOp::Add => i32::wrapping_add,
+        BinaryOp::Mul => i32::wrapping_mul,
+        _ => return None,
+    };
+    let (inner, outer_constant) = match (constant(data, lhs), constant(data, rhs)) {
+        (None, Some(c)) => (lhs, c),
+        (Some(c), None) => (rhs, c),
+        _ => return None,
+    };
+    let (inner_op, inner_lhs, inner_rhs) = binary(data, inner)?;
+    if inner_op != op {
+        return None;
+    }
+    let (var, inner_constant) = match (constant(data, inner_lhs), constant(data, inner_rhs)) {
+        (None, Some(c)) => (inner_lhs, c),
+        (Some(c), None) => (inner_rhs, c),
+        _ => return None,
+    };
+    Some((op, var, inner, combine(inner_constant, outer_constant)))
+}
+
+fn binary(data: &FunctionData, value: Value) -> Option<(BinaryOp, Value, Value)> {
+    if value.is_global() {
+        return None;
+    }
+    match data.dfg().value(value).kind() {
+        ValueKind::Binary(binary) => Some((binary.op(), binary.lhs(), binary.rhs())),
+        _ => None,
+    }
+}
+
+fn constant(data: &FunctionData, value: Value) -> Option<i32> {
+    if value.is_global() {
+        return None;
+    }
+    match data.dfg().value(value).kind() {
+        ValueKind::Integer(int) => Some(int.value()),
+        _ => None,
+    }
+}
@@ -0,0 +1,57 @@
+use koopa::ir::Program;
+use std::fmt;
+
+/// Size counters collected over one compilation, printed by `--stats`
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// Functions with a body (library declarations are not counted)
+    pub functions: usize,
+    /// Koopa IR instructions produced by the frontend
+    pub koopa_insts: usize,
+    /// RISC-V instructions emitted, if assembly was generated
+    pub riscv_insts: Option<usize>,
+    /// Largest stack frame in bytes, if assembly was generated
+    pub max_frame_size: Option<i32>,
+    /// (pass name, instructions removed) of every IR pass run at the
+    /// selected `-O` level, in order
+    pub removed_by_pass: Vec<(&'static str, usize)>,
+}
+
+impl Stats {
+    /// Records the size of the IR produced by the frontend
+    pub fn record_frontend(&mut self, program: &Program) {
+        self.functions = program
+            .funcs()
+            .values()
+            .filter(|func| func.layout().entry_bb().is_some())
+            .count();
+        self.koopa_insts = count_koopa_insts(program);
+    }
+}
+
+/// Counts the instructions in the bodies of all functions of `program`
+pub fn count_koopa_insts(program: &Program) -> usize {
+    program
+        .funcs()
+        .values()
+        .flat_map(|func| func.layout().bbs().nodes())
+        .map(|node| node.insts().len())
+        .sum()
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "functions:          {}", self.functions)?;
+        writeln!(f, "koopa instructions: {}", self.koopa_insts)?;
+        for (pass, removed) in &self.removed_by_pass {
+            writeln!(f, "  removed by {}: {}", pass, removed)?;
+        }
+        if let Some(riscv_insts) = self.riscv_insts {
+            writeln!(f, "riscv instructions: {}", riscv_insts)?;
+        }
+        if let Some(max_frame_size) = self.max_frame_size {
+            writeln!(f, "max frame size:     {} bytes", max_frame_size)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+use crate::cfg::bb_name;
+use koopa::ir::{BasicBlock, FunctionData, Program, TypeKind, Value, ValueKind};
+use std::collections::HashSet;
+use std::fmt;
+
+/// A malformed construct found by `verify`, with the function and basic
+/// block it is in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyError {
+    pub function: String,
+    pub bb: String,
+    pub kind: VerifyErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyErrorKind {
+    /// The block is empty or does not end with `br`, `jump` or `ret`
+    MissingTerminator,
+    /// A `br`, `jump` or `ret` that is not the last instruction of the
+    /// block (its index in the block)
+    TerminatorNotLast(usize),
+    /// An operand of the instruction at this index that is neither a
+    /// constant, a global, a parameter nor an instruction of the function
+    UndefinedValue(usize),
+    /// A `br` or `jump` at this index to a block that is not in the
+    /// layout of the function
+    UnknownTarget(usize),
+    /// A call at `index` with the wrong number of arguments
+    ArgumentCount {
+        index: usize,
+        callee: String,
+        expected: usize,
+        found: usize,
+    },
+    /// A call at `index` whose argument `arg` (counting from 0) does not
+    /// have the type of the parameter
+    ArgumentType {
+        index: usize,
+        callee: String,
+        arg: usize,
+        expected: String,
+        found: String,
+    },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "in {}, block {}: ", self.function, self.bb)?;
+        match &self.kind {
+            VerifyErrorKind::MissingTerminator => {
+                write!(f, "block does not end with br, jump or ret")
+            }
+            VerifyErrorKind::TerminatorNotLast(index) => {
+                write!(f, "instruction {} ends the block but is not last", index)
+            }
+            VerifyErrorKind::UndefinedValue(index) => {
+                write!(f, "instruction {} uses a value that is not defined", index)
+            }
+            VerifyErrorKind::UnknownTarget(index) => {
+                write!(f, "instruction {} targets an unknown block", index)
+            }
+            VerifyErrorKind::ArgumentCount {
+                index,
+                callee,
+                expected,
+                found,
+            } => write!(
+                f,
+                "instruction {} calls {} with {} argument(s), expected {}",
+                index, callee, found, expected
+            ),
+            VerifyErrorKind::ArgumentType {
+                index,
+                callee,
+                arg,
+                expected,
+                found,
+            } => write!(
+                f,
+                "instruction {} passes {} as argument {} of {}, expected {}",
+                index, found, arg, callee, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Checks the invariants the backend relies on: every basic block ends
+/// with exactly one terminator, every operand is defined and every call
+/// matches the signature of its callee.
+/// Returns all violations found
+pub fn verify(program: &Program) -> Result<(), Vec<VerifyError>> {
+    let mut errors = Vec::new();
+    for &func in program.func_layout() {
+        verify_function(program, program.func(func), &mut errors);
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn verify_function(program: &Program, func: &FunctionData, errors: &mut Vec<VerifyError>) {
+    let blocks: HashSet<BasicBlock> = func.layout().bbs().keys().copied().collect();
+    let defined: HashSet<Value> = func
+        .layout()
+        .bbs()
+        .nodes()
+        .flat_map(|node| node.insts().keys().copied())
+        .chain(func.params().iter().copied())
+        .collect();
+
+    for (&bb, node) in func.layout().bbs() {
+        let mut report = |kind| {
+            errors.push(VerifyError {
+                function: func.name().to_string(),
+                bb: bb_name(func, bb),
+                kind,
+            })
+        };
+        let last = node.insts().len().checked_sub(1);
+        for (index, &inst) in node.insts().keys().enumerate() {
+            let kind = func.dfg().value(inst).kind();
+            let is_terminator = matches!(
+                kind,
+                ValueKind::Branch(_) | ValueKind::Jump(_) | ValueKind::Return(_)
+            );
+            if is_terminator && Some(index) != last {
+                report(VerifyErrorKind::TerminatorNotLast(index));
+            }
+            if !is_terminator && Some(index) == last {
+                report(VerifyErrorKind::MissingTerminator);
+            }
+            let is_defined = |value: Value| {
+                value.is_global()
+                    || defined.contains(&value)
+                    || func
+                        .dfg()
+                        .values()
+                        .get(&value)
+                        .is_some_and(|data| data.kind().is_const())
+            };
+            if !kind.value_uses().all(is_defined) {
+                report(VerifyErrorKind::UndefinedValue(index));
+            }
+            if !kind.bb_uses().all(|target| blocks.contains(&target)) {
+                report(VerifyErrorKind::UnknownTarget(index));
+            }
+            if let ValueKind::Call(call) = kind {
+                for kind in check_call(program, func, call.callee(), call.args(), index) {
+                    report(kind);
+                }
+            }
+        }
+        if last.is_none() {
+            report(VerifyErrorKind::MissingTerminator);
+        }
+    }
+}
+
+/// Compares the arguments of a call with the parameters of `callee`
+fn check_call(
+    program: &Program,
+    func: &FunctionData,
+    callee: koopa::ir::Function,
+    args: &[Value],
+    index: usize,
+) -> Vec<VerifyErrorKind> {
+    let callee_data = program.func(callee);
+    let TypeKind::Function(params, _) = callee_data.ty().kind() else {
+        unreachable!("functions have a function type");
+    };
+    let callee = callee_data.name().to_string();
+    if params.len() != args.len() {
+        return vec![VerifyErrorKind::ArgumentCount {
+            index,
+            callee,
+            expected: params.len(),
+            found: args.len(),
+        }];
+    }
+    let mut errors = Vec::new();
+    for (arg, (&value, expected)) in args.iter().zip(params).enumerate() {
+        let found = if value.is_global() {
+            program.borrow_value(value).ty().clone()
+        } else {
+            match func.dfg().values().get(&value) {
+                Some(data) => data.ty().clone(),
+                // Reported as an undefined value
+                None => continue,
+            }
+        };
+        if &found != expected {
+            errors.push(VerifyErrorKind::ArgumentType {
+                index,
+                callee: callee.clone(),
+                arg,
+                expected: expected.to_string(),
+                found: found.to_string(),
+            });
+        }
+    }
+    errors
+}
@@ -0,0 +1,297 @@
+mod common;
+
+use common::{function_insts, perf_options, riscv_with};
+use compiler::backend::BackendOptions;
+
+/// Where the instruction right after `call func` puts the result of the
+/// call: a stack slot (`8(sp)`) or a register
+fn result_home<'a>(insts: &[&'a str], func: &str) -> &'a str {
+    let call = insts
+        .iter()
+        .position(|&inst| inst == format!("call {}", func))
+        .unwrap();
+    let save = insts[call + 1];
+    if let Some(slot) = save.strip_prefix("sw a0, ") {
+        slot
+    } else if let Some(reg) = save
+        .strip_prefix("mv ")
+        .and_then(|s| s.strip_suffix(", a0"))
+    {
+        reg
+    } else {
+        panic!("result of {} is not saved: {}", func, save);
+    }
+}
+
+/// The last instruction in `insts` that writes `reg`
+fn last_write<'a>(insts: &[&'a str], reg: &str) -> Option<&'a str> {
+    let dest = format!("{},", reg);
+    insts
+        .iter()
+        .rev()
+        .find(|inst| inst.split_whitespace().nth(1) == Some(dest.as_str()))
+        .copied()
+}
+
+#[test]
+fn nested_call_results_reach_the_outer_call() {
+    let src = r"
+        int g(int x) { return x + 1; }
+        int h(int y) { return y * 2; }
+        int f(int a, int b) { return a * 10 + b; }
+        int main() { int x = getint(); return f(g(x), h(x)); }";
+    for options in [BackendOptions::default(), perf_options()] {
+        let asm = riscv_with(src, &options);
+        let insts = function_insts(&asm, "main");
+        let g_home = result_home(&insts, "g");
+        let h_home = result_home(&insts, "h");
+        assert_ne!(g_home, h_home, "{}", asm);
+        // Between the last inner call and the outer one, a0 and a1 are
+        // last written from where the inner results were saved
+        let h_call = insts.iter().position(|&inst| inst == "call h").unwrap();
+        let f_call = insts.iter().position(|&inst| inst == "call f").unwrap();
+        for (reg, home) in [("a0", g_home), ("a1", h_home)] {
+            let write = last_write(&insts[h_call + 2..f_call], reg);
+            let expected = [
+                format!("lw {}, {}", reg, home),
+                format!("mv {}, {}", reg, home),
+            ];
+            assert!(
+                write.is_some_and(|write| expected.iter().any(|e| e == write)),
+                "{} is not set from {}:\n{}",
+                reg,
+                home,
+                asm
+            );
+        }
+    }
+}
+
+#[test]
+fn constant_reused_within_a_block() {
+    let src = r"
+        int main() {
+            int x = getint();
+            int a = x * 100;
+            a = a - 100;
+            a = a + 100;
+            a = a / 100;
+            return a % 100;
+        }";
+    let count_li = |asm: &str| {
+        function_insts(asm, "main")
+            .iter()
+            .filter(|inst| inst.starts_with("li ") && inst.ends_with(", 100"))
+            .count()
+    };
+    assert_eq!(count_li(&riscv_with(src, &BackendOptions::default())), 5);
+    let options = BackendOptions {
+        reuse_constants: true,
+        ..BackendOptions::default()
+    };
+    assert_eq!(count_li(&riscv_with(src, &options)), 1);
+}
+
+#[test]
+fn functions_in_source_order() {
+    let src = r"
+        int zeta() { return 1; }
+        int alpha() { return 2; }
+        int mid(int x) { return x; }
+        int main() { return zeta() + alpha() + mid(3); }";
+    let koopa = compiler::compile_to_koopa(src).unwrap();
+    let koopa_order: Vec<&str> = koopa
+        .lines()
+        .filter_map(|line| line.strip_prefix("fun @"))
+        .map(|line| line.split('(').next().unwrap())
+        .collect();
+    assert_eq!(koopa_order, ["zeta", "alpha", "mid", "main"]);
+    let asm = compiler::compile_to_riscv(src).unwrap();
+    let asm_order: Vec<&str> = asm
+        .lines()
+        .filter_map(|line| line.strip_suffix(':'))
+        .filter(|label| ["zeta", "alpha", "mid", "main"].contains(label))
+        .collect();
+    assert_eq!(asm_order, koopa_order);
+}
+
+#[test]
+fn only_non_empty_sections() {
+    let asm = compiler::compile_to_riscv("int g = 1;\nint h[2] = {1, 2};\n").unwrap();
+    assert!(asm.contains(".data"), "{}", asm);
+    assert!(!asm.contains(".text"), "{}", asm);
+    assert!(asm.contains("g:") && asm.contains(".word 1, 2"), "{}", asm);
+
+    let asm = compiler::compile_to_riscv("int main() { return 0; }").unwrap();
+    assert!(asm.contains(".text"), "{}", asm);
+    assert!(!asm.contains(".data"), "{}", asm);
+}
+
+#[test]
+fn multiplication_by_constants_is_strength_reduced() {
+    let src = r"
+        int main() {
+            int x = getint();
+            int a = x * 8;
+            int b = x * 1;
+            int c = 9 * x;
+            return a + b + c;
+        }";
+    let count = |insts: &[&str], op: &str| {
+        insts
+            .iter()
+            .filter(|inst| inst.starts_with(&format!("{} ", op)))
+            .count()
+    };
+    let asm = riscv_with(src, &BackendOptions::default());
+    assert_eq!(count(&function_insts(&asm, "main"), "mul"), 3, "{}", asm);
+
+    let options = BackendOptions {
+        strength_reduction: true,
+        ..BackendOptions::default()
+    };
+    for options in [options, perf_options()] {
+        let asm = riscv_with(src, &options);
+        let insts = function_insts(&asm, "main");
+        assert_eq!(count(&insts, "mul"), 0, "{}", asm);
+        // `x * 8` and `9 * x` shift by 3, `x * 1` needs no instruction
+        let shifts: Vec<&&str> = insts.iter().filter(|i| i.starts_with("slli ")).collect();
+        assert_eq!(shifts.len(), 2, "{}", asm);
+        assert!(shifts.iter().all(|i| i.ends_with(", 3")), "{}", asm);
+    }
+}
+
+/// The stack offsets `inst` accesses or takes the address of, e.g. 8 for
+/// `sw t0, 8(sp)` and `addi t1, sp, 8`
+fn sp_offset(inst: &str) -> Option<i32> {
+    if let Some(rest) = inst.strip_suffix("(sp)") {
+        return rest.rsplit(", ").next()?.parse().ok();
+    }
+    let operands: Vec<&str> = inst.strip_prefix("addi ")?.split(", ").collect();
+    match operands[..] {
+        [dest, "sp", offset] if dest != "sp" => offset.parse().ok(),
+        _ => None,
+    }
+}
+
+#[test]
+fn stack_arguments_do_not_overlap_locals() {
+    let src = r"
+        int f(int a, int b, int c, int d, int e, int g, int h, int i, int j, int k) {
+            return a + b + c + d + e + g + h + i + j * 100 + k * 1000;
+        }
+        int main() {
+            int x = getint();
+            int y = x + 1;
+            int z = y * 2;
+            int r = f(1, 2, 3, 4, 5, 6, 7, 8, 9, 10);
+            return r + x + y + z;
+        }";
+    for options in [BackendOptions::default(), perf_options()] {
+        let asm = riscv_with(src, &options);
+        let insts = function_insts(&asm, "main");
+        let call = insts.iter().position(|&inst| inst == "call f").unwrap();
+        // The ninth and tenth arguments are the last stores before the call
+        let stores: Vec<&str> = insts[..call]
+            .iter()
+            .filter(|inst| inst.starts_with("sw "))
+            .copied()
+            .collect();
+        assert_eq!(
+            stores[stores.len() - 2..],
+            ["sw t0, 0(sp)", "sw t0, 4(sp)"],
+            "{}",
+            asm
+        );
+        // Nothing else in main touches the outgoing argument area
+        let outgoing = insts
+            .iter()
+            .filter(|inst| sp_offset(inst).is_some_and(|offset| offset < 8))
+            .count();
+        assert_eq!(outgoing, 2, "{}", asm);
+
+        // The callee finds them right above its own frame
+        let insts = function_insts(&asm, "f");
+        let frame: i32 = insts[0]
+            .strip_prefix("addi sp, sp, -")
+            .unwrap()
+            .parse()
+            .unwrap();
+        for offset in [frame, frame + 4] {
+            assert!(
+                insts
+                    .iter()
+                    .any(|inst| inst.starts_with("lw ") && sp_offset(inst) == Some(offset)),
+                "{} is not read:\n{}",
+                offset,
+                asm
+            );
+        }
+    }
+}
+
+#[test]
+fn large_offsets_use_a_separate_scratch_register() {
+    let src = r"
+        int main() {
+            int a[600];
+            int x = getint();
+            int y = x + 3;
+            a[599] = y * 2;
+            return a[599] + y;
+        }";
+    for options in [BackendOptions::default(), perf_options()] {
+        let asm = riscv_with(src, &options);
+        let insts = function_insts(&asm, "main");
+        assert!(insts[0].starts_with("li "), "frame is not large:\n{}", asm);
+        let mut large = 0;
+        for window in insts.windows(3) {
+            // `li r, offset; add r, sp, r; sw value, 0(r)`
+            let [li, add, sw] = window else {
+                unreachable!()
+            };
+            let Some((reg, _)) = li.strip_prefix("li ").and_then(|s| s.split_once(", ")) else {
+                continue;
+            };
+            if *add != format!("add {}, sp, {}", reg, reg) {
+                continue;
+            }
+            let Some(value) = sw
+                .strip_prefix("sw ")
+                .and_then(|s| s.strip_suffix(&format!(", 0({})", reg)))
+            else {
+                continue;
+            };
+            assert_ne!(value, reg, "value clobbered by its address:\n{}", asm);
+            large += 1;
+        }
+        assert!(large > 0, "{}", asm);
+    }
+}
+
+#[test]
+fn large_zero_array_is_one_directive() {
+    let asm =
+        compiler::compile_to_riscv("int g[100];\nint h = 7;\nint main() { return h; }").unwrap();
+    let data: Vec<&str> = asm
+        .lines()
+        .map(str::trim)
+        .skip_while(|&line| line != ".data")
+        .take_while(|&line| line != ".text")
+        .filter(|line| !line.is_empty())
+        .collect();
+    assert_eq!(
+        data,
+        [
+            ".data",
+            ".globl g",
+            "g:",
+            ".zero 400",
+            ".globl h",
+            "h:",
+            ".word 7"
+        ],
+        "{}",
+        asm
+    );
+}
@@ -0,0 +1,79 @@
+#![allow(dead_code)]
+
+use compiler::backend::{self, BackendOptions};
+use compiler::opt::{self, OptLevel};
+use compiler::stats::Stats;
+use compiler::{frontend, interp};
+
+/// Translates `src` to Koopa IR, panicking on the first error
+pub fn translate(src: &str) -> koopa::ir::Program {
+    translate_with(src, &frontend::FrontendOptions::default())
+}
+
+/// Like `translate`, with frontend `options`
+pub fn translate_with(src: &str, options: &frontend::FrontendOptions) -> koopa::ir::Program {
+    let ast = frontend::parse(src).expect("parse error");
+    frontend::translate_to_koopa(ast, options)
+        .unwrap_or_else(|errors| panic!("compile errors: {:?}", errors))
+}
+
+/// Runs `src` with the interpreter and returns the exit value of `main`
+pub fn run(src: &str) -> i32 {
+    run_with_input(src, "").0
+}
+
+/// Runs `src` with the interpreter on `input` and returns the exit value of
+/// `main` and everything it printed
+pub fn run_with_input(src: &str, input: &str) -> (i32, String) {
+    interp::run(&translate(src), input).expect("runtime error")
+}
+
+/// Translates `src` and runs the IR passes of `level` on it
+pub fn optimize(src: &str, level: OptLevel) -> koopa::ir::Program {
+    let mut program = translate(src);
+    opt::optimize(&mut program, level, &mut Stats::default());
+    program
+}
+
+/// The Koopa IR text of `program`
+pub fn koopa_text(program: &koopa::ir::Program) -> String {
+    let mut text = Vec::new();
+    frontend::emit_ir(program, &mut text).unwrap();
+    String::from_utf8(text).unwrap()
+}
+
+/// The backend options `-perf` enables
+pub fn perf_options() -> BackendOptions {
+    BackendOptions {
+        reuse_constants: true,
+        allocate_registers: true,
+        strength_reduction: true,
+        peephole: true,
+        ..BackendOptions::default()
+    }
+}
+
+/// RISC-V assembly for `src`, generated with `options` (no IR passes)
+pub fn riscv_with(src: &str, options: &BackendOptions) -> String {
+    let mut asm = Vec::new();
+    backend::emit_riscv(&translate(src), &mut asm, options, &mut Stats::default()).unwrap();
+    String::from_utf8(asm).unwrap()
+}
+
+/// The instructions of `func` in `asm` up to its `ret`, without labels,
+/// directives and comments
+pub fn function_insts<'a>(asm: &'a str, func: &str) -> Vec<&'a str> {
+    let mut lines = asm
+        .lines()
+        .skip_while(|line| *line != format!("{}:", func))
+        .map(str::trim)
+        .filter(|line| !line.ends_with(':') && !line.starts_with('.') && !line.starts_with('#'));
+    let mut insts = Vec::new();
+    for line in lines.by_ref() {
+        insts.push(line);
+        if line == "ret" {
+            break;
+        }
+    }
+    insts
+}
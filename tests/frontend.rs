@@ -0,0 +1,754 @@
+mod common;
+
+use common::{run, run_with_input, translate, translate_with};
+use compiler::frontend::{self, FrontendOptions};
+use compiler::CompileError;
+use compiler::{interp, verify};
+
+#[test]
+fn goto_over_declaration() {
+    let src = "int main() { int y = 2; goto L; int x = 1; L: x = x + y; return x; }";
+    assert!(compiler::compile_to_koopa(src).is_ok());
+    assert_eq!(
+        run("int main() { goto L; const int a[2] = {3, 4}; L: return a[1]; }"),
+        4
+    );
+    assert_eq!(
+        run("int main() { int y = 2; goto L; int x; L: x = y; return x; }"),
+        2
+    );
+}
+
+#[test]
+fn constant_relational_conditions() {
+    let cases = [
+        ("<", 1),
+        (">", 0),
+        ("<=", 1),
+        (">=", 0),
+        ("==", 0),
+        ("!=", 1),
+    ];
+    for (op, expected) in cases {
+        let src = format!("int main() {{ if (1 {} 2) return 1; return 0; }}", op);
+        let koopa = compiler::compile_to_koopa(&src).unwrap();
+        assert!(
+            !koopa.contains("br "),
+            "`1 {} 2` was not folded:\n{}",
+            op,
+            koopa
+        );
+        assert_eq!(run(&src), expected, "1 {} 2", op);
+    }
+}
+
+#[test]
+fn dropped_if_arm_is_checked() {
+    let src = "int main() { if (0) { undeclared = 1; } return 0; }";
+    assert!(compiler::compile_to_koopa(src).is_err());
+    let src = "int main() { int x = 0; if (1) x = 1; else { x = 2; } return x; }";
+    let koopa = compiler::compile_to_koopa(src).unwrap();
+    assert!(!koopa.contains("store 2"), "{}", koopa);
+    assert_eq!(run(src), 1);
+}
+
+#[test]
+fn constant_division_by_zero() {
+    for init in ["1 / 0", "1 % 0", "0 % 0"] {
+        let src = format!("const int x = {}; int main() {{ return x; }}", init);
+        let error = compiler::compile_to_koopa(&src).unwrap_err().to_string();
+        assert!(error.contains("division by zero"), "{}: {}", init, error);
+        assert!(error.contains("'x'"), "{}: {}", init, error);
+    }
+    let src = "int main() { const int y = 2 / (1 - 1); return y; }";
+    let error = compiler::compile_to_koopa(src).unwrap_err().to_string();
+    assert!(error.contains("`2 / 0`"), "{}", error);
+}
+
+#[test]
+fn constant_arithmetic_wraps_like_runtime() {
+    let cases = [
+        ("2147483647", "+", "1"),
+        ("-2147483647 - 1", "-", "1"),
+        ("65536", "*", "65536"),
+        ("-2147483647 - 1", "/", "-1"),
+        ("-2147483647 - 1", "%", "-1"),
+    ];
+    for (lhs, op, rhs) in cases {
+        let folded = run(&format!(
+            "const int x = ({}) {} ({}); int main() {{ return x; }}",
+            lhs, op, rhs
+        ));
+        let src = format!(
+            "int main() {{ int a = getint(); int b = getint(); return a {} b; }}",
+            op
+        );
+        let input = format!(
+            "{} {}",
+            run(&format!("int main() {{ return {}; }}", lhs)),
+            rhs
+        );
+        let (runtime, _) = run_with_input(&src, &input);
+        assert_eq!(folded, runtime, "({}) {} ({})", lhs, op, rhs);
+    }
+    assert_eq!(
+        run("const int x = 2147483647 + 1; int main() { return x; }"),
+        i32::MIN
+    );
+    assert_eq!(
+        run("const int x = -(-2147483647 - 1); int main() { return x; }"),
+        i32::MIN
+    );
+}
+
+#[test]
+fn sizeof_is_an_identifier_by_default() {
+    assert_eq!(run("int main() { int sizeof = 3; return sizeof; }"), 3);
+    let src = "int sizeof(int x) { return x + 1; } int main() { return sizeof(4); }";
+    assert_eq!(run(src), 5);
+}
+
+#[test]
+fn sizeof_extension_counts_bytes() {
+    let options = FrontendOptions {
+        sizeof: true,
+        ..FrontendOptions::default()
+    };
+    // One array's dimension sizes another
+    let src = r"
+        int a[4][3];
+        int b[sizeof(a) / sizeof(a[0])];
+        int main() {
+            int n = sizeof(b) / sizeof(b[0]);
+            return sizeof(b) * 100 + sizeof(a[0]) * 10 + n;
+        }";
+    let program = translate_with(src, &options);
+    assert_eq!(interp::run(&program, "").unwrap().0, 16 * 100 + 12 * 10 + 4);
+
+    // A declared `sizeof` still wins
+    let src = "int sizeof(int x) { return x + 1; } int main() { return sizeof(4); }";
+    let program = translate_with(src, &options);
+    assert_eq!(interp::run(&program, "").unwrap().0, 5);
+}
+
+#[test]
+fn array_parameter_with_inner_dimensions() {
+    let src = r"
+        int f(int a[][3], int i, int j) { return a[i][j]; }
+        int g(int a[2][3]) { return a[1][0]; }
+        int main() {
+            int b[2][3] = {{1, 2, 3}, {4, 5, 6}};
+            return f(b, 1, 2) * 10 + g(b);
+        }";
+    let koopa = compiler::compile_to_koopa(src).unwrap();
+    assert!(
+        koopa.contains("fun @f(@a: *[i32, 3], @i: i32, @j: i32)"),
+        "{}",
+        koopa
+    );
+    // The explicit first dimension is dropped like an empty one
+    assert!(koopa.contains("fun @g(@a: *[i32, 3])"), "{}", koopa);
+    // getptr steps over whole rows, getelemptr picks the element
+    let getptr = koopa.find("getptr %").unwrap();
+    let getelemptr = koopa.find("getelemptr %").unwrap();
+    assert!(getptr < getelemptr, "{}", koopa);
+    assert_eq!(run(src), 64);
+}
+
+#[test]
+fn partial_initializers_are_zero_filled() {
+    let src = r"
+        int g[2][2] = {{1}, {2}};
+        int h[3] = {1};
+        int main() {
+            int a[2][2] = {{1}, {2}};
+            int b[3] = {7};
+            return a[0][0] + a[0][1] * 2 + a[1][0] * 4 + a[1][1] * 8 + b[1] + b[2]
+                + g[0][1] + g[1][1] + h[1] + h[2];
+        }";
+    let koopa = compiler::compile_to_koopa(src).unwrap();
+    assert!(
+        koopa.contains("global @g = alloc [[i32, 2], 2], {{1, 0}, {2, 0}}"),
+        "{}",
+        koopa
+    );
+    assert!(
+        koopa.contains("global @h = alloc [i32, 3], {1, 0, 0}"),
+        "{}",
+        koopa
+    );
+    // Local arrays store every element, in row-major order
+    let stores: Vec<&str> = koopa
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("store "))
+        .map(|store| store.split(',').next().unwrap())
+        .collect();
+    assert_eq!(stores, ["1", "0", "2", "0", "7", "0", "0"]);
+    assert_eq!(run(src), 9);
+}
+
+/// Koopa IR text split into (block label, instructions)
+fn blocks(koopa: &str) -> Vec<(&str, Vec<&str>)> {
+    let mut blocks: Vec<(&str, Vec<&str>)> = Vec::new();
+    for line in koopa.lines() {
+        if line.starts_with('%') && line.ends_with(':') {
+            blocks.push((line.trim_end_matches(':'), Vec::new()));
+        } else if line.starts_with("  ") {
+            blocks.last_mut().unwrap().1.push(line.trim());
+        }
+    }
+    blocks
+}
+
+#[test]
+fn return_inside_loop() {
+    let src = "int main() { int i = getint(); while (i < 5) { i = i + 1; return i; } return 0; }";
+    let program = translate(src);
+    assert!(verify::verify(&program).is_ok());
+    let koopa = compiler::compile_to_koopa(src).unwrap();
+    let (_, body) = blocks(&koopa)
+        .into_iter()
+        .find(|(label, _)| label.starts_with("%while_body"))
+        .unwrap();
+    // The body ends with its return and has no back edge
+    assert!(body.last().unwrap().starts_with("ret"), "{}", koopa);
+    assert!(
+        !body.iter().any(|inst| inst.starts_with("jump")),
+        "{}",
+        koopa
+    );
+    assert_eq!(interp::run(&program, "1").unwrap().0, 2);
+
+    // A return in only one path keeps the back edge for the other
+    let src = r"
+        int main() {
+            int i = 0;
+            while (1) {
+                if (i == 3) return i;
+                i = i + 1;
+            }
+            return 0;
+        }";
+    let program = translate(src);
+    assert!(verify::verify(&program).is_ok());
+    let koopa = compiler::compile_to_koopa(src).unwrap();
+    let back_edge = blocks(&koopa).into_iter().any(|(label, insts)| {
+        !label.starts_with("%entry") && insts.last().unwrap().starts_with("jump %while_cond")
+    });
+    assert!(back_edge, "{}", koopa);
+    assert_eq!(run(src), 3);
+}
+
+#[test]
+fn void_call_in_comma_expression() {
+    let src = "int main() { int x = 5; putint(1), putint(2); return (putint(3), x); }";
+    assert_eq!(run_with_input(src, ""), (5, "123".to_string()));
+    // The last operand gives the value, so it cannot be a void call
+    let src = "int main() { int x = 5; return (x, putint(3)); }";
+    let error = compiler::compile_to_koopa(src).unwrap_err().to_string();
+    assert!(error.contains("putint"), "{}", error);
+}
+
+#[test]
+fn return_statements_match_the_return_type() {
+    let src = "void f() { return 5; } int main() { f(); return 0; }";
+    assert_eq!(
+        compiler::compile_to_koopa(src),
+        Err(CompileError::ReturnValueFromVoid("f".to_string()))
+    );
+    let src = "int g() { return; } int main() { return g(); }";
+    assert_eq!(
+        compiler::compile_to_koopa(src),
+        Err(CompileError::MissingReturnValue("g".to_string()))
+    );
+    let src = "void f() { return; } int main() { f(); return 0; }";
+    let koopa = compiler::compile_to_koopa(src).unwrap();
+    let (_, body) = blocks(&koopa).into_iter().next().unwrap();
+    assert_eq!(body, ["ret"]);
+}
+
+#[test]
+fn assignment_to_parameter() {
+    let src = r"
+        int f(int x) {
+            int y = x;
+            x = x + 10;
+            return x * 100 + y;
+        }
+        int main() { return f(getint()); }";
+    let koopa = compiler::compile_to_koopa(src).unwrap();
+    // The parameter is copied into its slot, which the assignment updates
+    assert!(koopa.contains("store @x, %x"), "{}", koopa);
+    let (_, body) = blocks(&koopa).into_iter().next().unwrap();
+    let stores: Vec<&&str> = body.iter().filter(|inst| inst.ends_with(", %x")).collect();
+    assert_eq!(stores.len(), 2, "{}", koopa);
+    assert_eq!(run_with_input(src, "3").0, 1303);
+}
+
+#[test]
+fn integer_literal_out_of_range() {
+    for literal in ["2147483648", "0x100000000", "040000000000"] {
+        let src = format!("int main() {{\n  return {};\n}}", literal);
+        assert_eq!(
+            compiler::compile_to_koopa(&src),
+            Err(CompileError::Syntax {
+                line: 2,
+                col: 10,
+                message: "integer literal out of range for int".to_string()
+            }),
+            "{}",
+            literal
+        );
+    }
+    assert_eq!(run("int main() { return 2147483647; }"), i32::MAX);
+    // `-2147483648` is the negation of an out-of-range literal
+    assert!(compiler::compile_to_koopa("int main() { return -2147483648; }").is_err());
+}
+
+#[test]
+fn non_constant_array_size() {
+    let src = "int main() { int n = 5; int a[2][n]; return 0; }";
+    let error = compiler::compile_to_koopa(src).unwrap_err();
+    let CompileError::ArraySizeNotConstant {
+        name, dimension, ..
+    } = &error
+    else {
+        panic!("unexpected error: {}", error);
+    };
+    assert_eq!((name.as_str(), *dimension), ("a", 2));
+    assert!(
+        error
+            .to_string()
+            .starts_with("array size must be a constant expression"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn constant_index_into_const_array() {
+    let src = r"
+        const int V[3] = {4, 5, 6};
+        const int M[2][2] = {{1, 2}, {3, 4}};
+        int main() {
+            const int L[2][3] = {{7}, {8, 9}};
+            int x = M[1][0];
+            int y = V[2];
+            int a[M[1][1]];
+            return x * 100 + y * 10 + L[1][1];
+        }";
+    let koopa = compiler::compile_to_koopa(src).unwrap();
+    assert!(koopa.contains("store 3, @x_1"), "{}", koopa);
+    assert!(koopa.contains("store 6, @y_1"), "{}", koopa);
+    assert!(koopa.contains("@a_1 = alloc [i32, 4]"), "{}", koopa);
+    assert_eq!(run(src), 369);
+}
+
+#[test]
+fn loops_with_empty_bodies() {
+    let src = r"
+        int main() {
+            while (getint());
+            while (getint()) {}
+            for (; getint();) {}
+            return 7;
+        }";
+    let program = translate(src);
+    assert!(verify::verify(&program).is_ok());
+    let koopa = compiler::compile_to_koopa(src).unwrap();
+    let blocks = blocks(&koopa);
+    let mut conditions = 0;
+    for (label, insts) in &blocks {
+        if label.contains("_body_") {
+            // Only the back edge, to the condition or the `for` step
+            assert_eq!(insts.len(), 1, "{}", koopa);
+            assert!(insts[0].starts_with("jump %"), "{}", koopa);
+        }
+        if label.contains("_cond_") {
+            assert!(insts.last().unwrap().starts_with("br "), "{}", koopa);
+            conditions += 1;
+        }
+    }
+    assert_eq!(conditions, 3, "{}", koopa);
+    assert_eq!(interp::run(&program, "1 1 0 1 0 0").unwrap().0, 7);
+
+    // `for (;;);` loops back to itself without a condition
+    let src = "int main() { for (;;); }";
+    assert!(verify::verify(&translate(src)).is_ok());
+    let koopa = compiler::compile_to_koopa(src).unwrap();
+    assert!(koopa.contains("jump %for_cond"), "{}", koopa);
+    assert!(!koopa.contains("br "), "{}", koopa);
+}
+
+#[test]
+fn local_constants_are_not_allocated() {
+    let src = r"
+        int main() {
+            const int k = 2 * 3;
+            int a[k];
+            a[k - 1] = k;
+            return a[5];
+        }";
+    let koopa = compiler::compile_to_koopa(src).unwrap();
+    let allocs: Vec<&str> = koopa
+        .lines()
+        .filter(|line| line.contains("alloc"))
+        .collect();
+    assert_eq!(allocs, ["  @a_1 = alloc [i32, 6]"], "{}", koopa);
+    assert!(!koopa.contains("@k"), "{}", koopa);
+    assert_eq!(run(src), 6);
+
+    let src = "int main() { const int k = 2 * 3; k = 1; return k; }";
+    assert_eq!(
+        compiler::compile_to_koopa(src),
+        Err(CompileError::AssignToConst("k".to_string()))
+    );
+}
+
+#[test]
+fn logical_not() {
+    for (operand, expected) in [("0", 1), ("5", 0), ("-1", 0)] {
+        let folded = run(&format!(
+            "const int c = !({}); int main() {{ return c; }}",
+            operand
+        ));
+        let src = "int main() { int x = getint(); return !x; }";
+        let (runtime, _) = run_with_input(src, operand);
+        assert_eq!((folded, runtime), (expected, expected), "!{}", operand);
+    }
+}
+
+#[test]
+fn braceless_bodies() {
+    let src = r"
+        int main() {
+            int x = 0, i = 0;
+            if (x) x = 1; else x = 2;
+            while (i < 3) i = i + 1;
+            for (; i < 5;) i = i + 1;
+            if (x == 2) if (i == 0) x = 10; else x = x + i;
+            return x * 10 + i;
+        }";
+    // The dangling `else` belongs to the inner `if`
+    assert_eq!(run(src), 75);
+    let ast = frontend::parse("int main() { if (1) if (0) return 1; else return 2; }").unwrap();
+    let tree = compiler::ast::pretty_print(&ast);
+    let inner_if = tree
+        .lines()
+        .filter(|line| line.trim() == "If")
+        .nth(1)
+        .unwrap();
+    let else_line = tree.lines().find(|line| line.trim() == "Else").unwrap();
+    assert_eq!(
+        else_line.len() - else_line.trim_start().len(),
+        inner_if.len() - inner_if.trim_start().len() + 2,
+        "{}",
+        tree
+    );
+}
+
+#[test]
+fn declaration_as_body_is_rejected() {
+    for body in [
+        "if (c) int x;",
+        "if (c) ; else int x;",
+        "while (c) const int x = 1;",
+        "for (;;) int x;",
+    ] {
+        let src = format!("int main() {{ int c = 1; {} return 0; }}", body);
+        let Err(CompileError::Syntax { message, .. }) = frontend::parse(&src) else {
+            panic!("{} was accepted", body);
+        };
+        assert!(
+            message.contains("put the declaration in a block"),
+            "{}",
+            message
+        );
+    }
+}
+
+#[test]
+fn only_called_library_functions_are_declared() {
+    let koopa = compiler::compile_to_koopa("int main() { putint(1); return 0; }").unwrap();
+    let decls: Vec<&str> = koopa
+        .lines()
+        .filter(|line| line.starts_with("decl "))
+        .collect();
+    assert_eq!(decls, ["decl @putint(i32)"]);
+    let koopa = compiler::compile_to_koopa("int main() { return 0; }").unwrap();
+    assert!(!koopa.contains("decl "), "{}", koopa);
+}
+
+#[test]
+fn comparison_results_assigned_to_variables() {
+    let src = r"
+        int main() {
+            int a = getint(), c = getint();
+            int lt = (a < c), gt = (a > c), le = (a <= c);
+            int ge, eq, ne;
+            ge = a >= c;
+            eq = a == c;
+            ne = a != c;
+            putint(lt); putint(gt); putint(le); putint(ge); putint(eq); putint(ne);
+            return lt + gt + le + ge + eq + ne;
+        }";
+    for (input, expected) in [("1 2", "101001"), ("2 1", "010101"), ("2 2", "001110")] {
+        // Every result is exactly 0 or 1, three of them 1
+        assert_eq!(
+            run_with_input(src, input),
+            (3, expected.to_string()),
+            "{}",
+            input
+        );
+    }
+}
+
+#[test]
+fn branchless_logical_operators() {
+    // Both operands are 0/1 and the right one has no side effect
+    let src = "int main() { int a = getint(), b = getint(); return (a < b && b < 5) * 2 + (a == b || b > 3); }";
+    let koopa = compiler::compile_to_koopa(src).unwrap();
+    assert!(!koopa.contains("br "), "{}", koopa);
+    assert!(
+        koopa.contains(" = and ") && koopa.contains(" = or "),
+        "{}",
+        koopa
+    );
+    for (input, expected) in [("1 2", 2), ("1 7", 1), ("3 3", 1), ("4 1", 0)] {
+        assert_eq!(run_with_input(src, input).0, expected, "{}", input);
+    }
+
+    // A call on the right still short-circuits
+    let src = r"
+        int f() { putint(9); return 1; }
+        int main() { int a = getint(), b = getint(); return a < b || f() > 0; }";
+    let koopa = compiler::compile_to_koopa(src).unwrap();
+    assert!(koopa.contains("br "), "{}", koopa);
+    assert_eq!(run_with_input(src, "1 2"), (1, String::new()));
+    assert_eq!(run_with_input(src, "2 1"), (1, "9".to_string()));
+
+    // A non-boolean operand is not 0/1, so `&&` still compares it with 0
+    let src = "int main() { int a = getint(), b = getint(); return a && b < 5; }";
+    assert_eq!(run_with_input(src, "6 1").0, 1);
+}
+
+#[test]
+fn comments_are_skipped() {
+    let src = "// leading line comment\n\
+        /* a block comment\n   spanning lines */\n\
+        int main() { /* inline */ int a = 2; // trailing\n\
+        /**/ return a /* between */ * 21; /* ** / */ }\n\
+        // last line without a newline";
+    assert_eq!(run(src), 42);
+    // `//` inside a block comment does not hide its end
+    assert_eq!(run("int main() { /* // */ return 1; }"), 1);
+}
+
+#[test]
+fn hexadecimal_and_octal_literals() {
+    assert_eq!(run("int main() { return 0xff; }"), 255);
+    assert_eq!(run("int main() { return 0XfF == 255; }"), 1);
+    assert_eq!(run("int main() { return 010; }"), 8);
+    assert_eq!(run("int main() { return 0; }"), 0);
+    // Constants fold the same way whatever the spelling
+    let decimal =
+        compiler::compile_to_koopa("int main() { int x = getint(); return x + 255 + 8; }");
+    let other =
+        compiler::compile_to_koopa("int main() { int x = getint(); return x + 0xff + 010; }");
+    assert_eq!(decimal, other);
+    let koopa =
+        compiler::compile_to_koopa("const int n = 0x10 + 010; int a[n]; int main() { return 0; }")
+            .unwrap();
+    assert!(koopa.contains("[i32, 24]"), "{}", koopa);
+}
+
+#[test]
+fn do_while_runs_the_body_first() {
+    let src = r"
+        int main() {
+            int i = getint();
+            int n = 0;
+            do {
+                n = n + 1;
+                i = i + 1;
+            } while (i < 3);
+            return n;
+        }";
+    let koopa = compiler::compile_to_koopa(src).unwrap();
+    let blocks = blocks(&koopa);
+    let position = |prefix: &str| {
+        blocks
+            .iter()
+            .position(|(label, _)| label.starts_with(prefix))
+            .unwrap()
+    };
+    let (body, cond) = (position("%do_body"), position("%do_cond"));
+    assert!(body < cond, "{}", koopa);
+    // The entry falls into the body, not the condition
+    assert!(
+        blocks[0].1.last().unwrap().starts_with("jump %do_body"),
+        "{}",
+        koopa
+    );
+    assert!(
+        blocks[cond].1.iter().any(|inst| inst.contains(" = lt ")),
+        "{}",
+        koopa
+    );
+
+    // The body runs once even if the condition is false from the start
+    assert_eq!(run_with_input(src, "10").0, 1);
+    assert_eq!(run_with_input(src, "0").0, 3);
+
+    // `continue` goes to the condition and `break` leaves the loop
+    let src = r"
+        int main() {
+            int i = 0, sum = 0;
+            do {
+                i = i + 1;
+                if (i == 2) continue;
+                if (i == 5) break;
+                sum = sum + i;
+            } while (i < 10);
+            return sum;
+        }";
+    assert_eq!(run(src), 1 + 3 + 4);
+}
+
+#[test]
+fn bitwise_operators_fold_like_runtime() {
+    let cases = [
+        ("12", "&", "10"),
+        ("12", "|", "10"),
+        ("12", "^", "10"),
+        ("-16", ">>", "2"),
+        ("-1", ">>", "31"),
+        ("1", "<<", "31"),
+        ("1", "<<", "33"),
+        ("-8", ">>", "34"),
+        ("1", "<<", "-1"),
+    ];
+    for (lhs, op, rhs) in cases {
+        let folded = run(&format!(
+            "const int x = ({}) {} ({}); int main() {{ return x; }}",
+            lhs, op, rhs
+        ));
+        let src = format!(
+            "int main() {{ int a = getint(); int b = getint(); return a {} b; }}",
+            op
+        );
+        let (runtime, _) = run_with_input(&src, &format!("{} {}", lhs, rhs));
+        assert_eq!(folded, runtime, "({}) {} ({})", lhs, op, rhs);
+    }
+    // `>>` is arithmetic and shift amounts are taken modulo 32
+    assert_eq!(run("const int x = -16 >> 2; int main() { return x; }"), -4);
+    assert_eq!(run("const int x = 1 << 33; int main() { return x; }"), 2);
+
+    // `~` folds and runs the same way
+    assert_eq!(run("const int x = ~5; int main() { return x; }"), -6);
+    assert_eq!(
+        run_with_input("int main() { return ~getint(); }", "-1").0,
+        0
+    );
+}
+
+#[test]
+fn bitwise_operator_precedence() {
+    // Shifts bind looser than `+`, `&` looser than `==`, then `^` and `|`
+    let cases = [
+        ("1 << 2 + 1", 8),
+        ("16 >> 1 + 1", 4),
+        ("1 << 2 < 5", 1),
+        ("3 & 1 == 1", 1),
+        ("2 & 2 == 2", 0),
+        ("1 | 2 ^ 3", 1),
+        ("6 ^ 3 & 5", 7),
+        ("1 | 0 && 0", 0),
+        ("~1 + 2", 0),
+    ];
+    for (expr, expected) in cases {
+        let folded = run(&format!(
+            "const int x = {}; int main() {{ return x; }}",
+            expr
+        ));
+        assert_eq!(folded, expected, "{}", expr);
+        // The same expression with the operands only known at runtime
+        let mut runtime = String::new();
+        let mut input = String::new();
+        for token in expr.split(' ') {
+            match token.trim_start_matches('~').parse::<i32>() {
+                Ok(value) => {
+                    let prefix = &token[..token.len() - token.trim_start_matches('~').len()];
+                    runtime.push_str(prefix);
+                    runtime.push_str("getint()");
+                    input.push_str(&format!("{} ", value));
+                }
+                Err(_) => runtime.push_str(token),
+            }
+            runtime.push(' ');
+        }
+        let src = format!("int main() {{ return {}; }}", runtime);
+        assert_eq!(run_with_input(&src, &input).0, expected, "{}", src);
+    }
+}
+
+#[test]
+fn compound_assignment() {
+    // The index of `a[f()] += 3` is evaluated once
+    let src = r"
+        int n = 0;
+        int f() { n = n + 1; return 1; }
+        int main() {
+            int a[2] = {10, 20};
+            a[f()] += 3;
+            return a[1] * 10 + n;
+        }";
+    assert_eq!(run(src), 231);
+
+    let src = r"
+        int main() {
+            int x = getint();
+            int q = x, r = x;
+            q /= 4;
+            r %= 4;
+            x -= 1; x *= 3; x &= 14; x |= 1; x ^= 3; x <<= 2; x >>= 1;
+            putint(q); putch(32); putint(r);
+            return x;
+        }";
+    assert_eq!(run_with_input(src, "-7"), (20, "-1 -3".to_string()));
+    assert_eq!(run_with_input(src, "13"), (12, "3 1".to_string()));
+
+    let src = "int main() { const int k = 1; k += 1; return k; }";
+    assert_eq!(
+        compiler::compile_to_koopa(src),
+        Err(CompileError::AssignToConst("k".to_string()))
+    );
+}
+
+#[test]
+fn compound_assignment_tokens() {
+    let mut output = Vec::new();
+    frontend::emit_tokens("x <<= 1 >>= += -= *= /= %= &= |= ^=", &mut output).unwrap();
+    let texts: Vec<String> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|line| line.split('\t').skip(2).collect::<Vec<_>>().join(" "))
+        .collect();
+    assert_eq!(
+        texts,
+        [
+            "ident x",
+            "punct <<=",
+            "int 1",
+            "punct >>=",
+            "punct +=",
+            "punct -=",
+            "punct *=",
+            "punct /=",
+            "punct %=",
+            "punct &=",
+            "punct |=",
+            "punct ^=",
+        ]
+    );
+}
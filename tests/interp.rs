@@ -0,0 +1,60 @@
+mod common;
+
+use common::{run, run_with_input};
+
+#[test]
+fn alloc_in_loop_reuses_its_slot() {
+    // `x` is read before it is assigned in the later iterations, so it
+    // still holds the value stored in the previous one, as on the target
+    let src = r"
+        int main() {
+            int i = 0, s = 0;
+            while (i < 3) {
+                int x;
+                if (i > 0) s = s + x;
+                x = 5;
+                i = i + 1;
+            }
+            return s;
+        }";
+    assert_eq!(run(src), 10);
+}
+
+#[test]
+fn array_in_long_loop() {
+    let src = r"
+        int main() {
+            int i = 0, s = 0;
+            while (i < 20000) {
+                int a[1000];
+                a[i % 1000] = i;
+                s = s + a[i % 1000] % 7;
+                i = i + 1;
+            }
+            return s;
+        }";
+    let expected: i32 = (0..20000).map(|i| i % 7).sum();
+    assert_eq!(run(src), expected);
+}
+
+#[test]
+fn recursion_and_io() {
+    let src = r"
+        int fib(int n) {
+            if (n < 2) return n;
+            return fib(n - 1) + fib(n - 2);
+        }
+        int main() {
+            int n = getint();
+            putint(fib(n));
+            putch(10);
+            return n;
+        }";
+    assert_eq!(run_with_input(src, "15"), (15, "610\n".to_string()));
+}
+
+#[test]
+fn runtime_division_by_zero_is_an_error() {
+    let program = common::translate("int main() { int x = 0; return 1 / x; }");
+    assert!(compiler::interp::run(&program, "").is_err());
+}
@@ -0,0 +1,123 @@
+mod common;
+
+use common::{koopa_text, optimize};
+use compiler::interp;
+use compiler::opt::OptLevel;
+
+#[test]
+fn constants_are_folded() {
+    let src = "int main() { int x = getint(); return (2 + 3) * 4 - 6 / 2 + x; }";
+    let program = optimize(src, OptLevel::O1);
+    let koopa = koopa_text(&program);
+    for op in ["mul", "sub", "div"] {
+        assert!(!koopa.contains(op), "`{}` was not folded:\n{}", op, koopa);
+    }
+    assert!(koopa.contains("add 17, %"), "{}", koopa);
+    assert_eq!(interp::run(&program, "10").unwrap().0, 27);
+}
+
+#[test]
+fn folding_wraps_like_the_interpreter() {
+    let src = "int main() { return 2147483647 + 1 + (-2147483647 - 1) / -1 + 7 % -3; }";
+    let unoptimized = common::run(src);
+    let program = optimize(src, OptLevel::O1);
+    assert!(!koopa_text(&program).contains("add"));
+    assert_eq!(interp::run(&program, "").unwrap().0, unoptimized);
+}
+
+#[test]
+fn division_by_zero_is_not_folded() {
+    let program = optimize("int main() { return 1 / 0; }", OptLevel::O1);
+    assert!(koopa_text(&program).contains("div 1, 0"));
+}
+
+#[test]
+fn unused_pure_instructions_are_removed() {
+    let src = "int main() { int x = getint(); x * 2; x / 0; putint(x + 1); return x; }";
+    let program = optimize(src, OptLevel::O1);
+    let koopa = koopa_text(&program);
+    assert!(
+        !koopa.contains("mul") && !koopa.contains("div"),
+        "{}",
+        koopa
+    );
+    assert!(koopa.contains("call @putint"), "{}", koopa);
+    assert_eq!(interp::run(&program, "4").unwrap(), (4, "5".to_string()));
+}
+
+#[test]
+fn algebraic_identities() {
+    for (expr, removed) in [
+        ("x * 0", "mul"),
+        ("0 * x", "mul"),
+        ("x + 0", "add"),
+        ("0 + x", "add"),
+        ("x - 0", "sub"),
+        ("x * 1", "mul"),
+        ("1 * x", "mul"),
+        ("x / 1", "div"),
+    ] {
+        let src = format!("int main() {{ int x = getint(); return {}; }}", expr);
+        let program = optimize(&src, OptLevel::O1);
+        let koopa = koopa_text(&program);
+        assert!(
+            !koopa.contains(removed),
+            "{} was not simplified:\n{}",
+            expr,
+            koopa
+        );
+        let expected = if expr.contains('0') && expr.contains('*') {
+            0
+        } else {
+            7
+        };
+        assert_eq!(interp::run(&program, "7").unwrap().0, expected, "{}", expr);
+    }
+}
+
+#[test]
+fn multiplying_a_call_by_zero_keeps_the_call() {
+    let src = "int main() { return getint() * 0; }";
+    let program = optimize(src, OptLevel::O1);
+    assert!(koopa_text(&program).contains("call @getint"));
+    // The call still consumes its input
+    let src = "int main() { getint() * 0; return getint(); }";
+    let program = optimize(src, OptLevel::O1);
+    assert_eq!(interp::run(&program, "1 2").unwrap().0, 2);
+}
+
+#[test]
+fn constants_in_add_and_multiply_chains() {
+    for (expr, folded, input, expected) in [
+        ("x + 2 + 3", ", 5", 10, 15),
+        ("2 + x + 3", ", 5", 10, 15),
+        ("x * 2 * 4", ", 8", 10, 80),
+    ] {
+        let src = format!("int main() {{ int x = getint(); return {}; }}", expr);
+        let program = optimize(&src, OptLevel::O2);
+        let koopa = koopa_text(&program);
+        let body: Vec<&str> = koopa
+            .lines()
+            .filter(|line| line.contains(" = add ") || line.contains(" = mul "))
+            .collect();
+        assert_eq!(body.len(), 1, "{}:\n{}", expr, koopa);
+        assert!(body[0].ends_with(folded), "{}:\n{}", expr, koopa);
+        let result = interp::run(&program, &input.to_string()).unwrap().0;
+        assert_eq!(result, expected, "{}", expr);
+    }
+}
+
+#[test]
+fn no_reassociation_across_other_operators() {
+    for (expr, input) in [("x / 2 * 4", 5), ("x - 2 + 3", 5), ("x % 3 * 2", 7)] {
+        let src = format!("int main() {{ int x = getint(); return {}; }}", expr);
+        let expected = common::run_with_input(&src, &input.to_string()).0;
+        let program = optimize(&src, OptLevel::O2);
+        assert_eq!(
+            interp::run(&program, &input.to_string()).unwrap().0,
+            expected,
+            "{}",
+            expr
+        );
+    }
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use common::translate;
+use compiler::verify::{verify, VerifyError, VerifyErrorKind};
+use koopa::ir::builder_traits::*;
+use koopa::ir::{BinaryOp, FunctionData, Program, Type};
+
+/// The kinds of the errors `verify` reports for `program`
+fn error_kinds(program: &Program) -> Vec<VerifyErrorKind> {
+    verify(program)
+        .unwrap_err()
+        .into_iter()
+        .map(|error: VerifyError| error.kind)
+        .collect()
+}
+
+#[test]
+fn generated_programs_are_valid() {
+    let program = translate(
+        r"
+        int g[2] = {1, 2};
+        int f(int a[], int n) { return a[n]; }
+        int main() {
+            int i = 0;
+            while (i < 2) { if (i) break; i = i + 1; }
+            return f(g, i) + getint();
+        }",
+    );
+    assert_eq!(verify(&program), Ok(()));
+}
+
+#[test]
+fn removed_terminator_is_reported() {
+    let mut program = translate("int main() { return 0; }");
+    let main = *program.func_layout().last().unwrap();
+    let func = program.func_mut(main);
+    let entry = func.layout().entry_bb().unwrap();
+    func.layout_mut().bb_mut(entry).insts_mut().pop_back();
+    let errors = verify(&program).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].function, "@main");
+    assert_eq!(errors[0].bb, "%entry_0");
+    assert_eq!(errors[0].kind, VerifyErrorKind::MissingTerminator);
+}
+
+#[test]
+fn malformed_functions_built_by_hand() {
+    let mut program = Program::new();
+    let callee = program.new_func(FunctionData::new(
+        "@callee".into(),
+        vec![Type::get_i32()],
+        Type::get_i32(),
+    ));
+    let main = program.new_func(FunctionData::new(
+        "@main".into(),
+        Vec::new(),
+        Type::get_i32(),
+    ));
+    let func = program.func_mut(main);
+    let entry = func.dfg_mut().new_bb().basic_block(Some("%entry".into()));
+    let missing = func.dfg_mut().new_bb().basic_block(Some("%missing".into()));
+    let call = func.dfg_mut().new_value().call(callee, Vec::new());
+    let ret = func.dfg_mut().new_value().ret(Some(call));
+    let jump = func.dfg_mut().new_value().jump(missing);
+    func.layout_mut().bbs_mut().extend([entry]);
+    func.layout_mut()
+        .bb_mut(entry)
+        .insts_mut()
+        .extend([call, ret, jump]);
+    assert_eq!(
+        error_kinds(&program),
+        [
+            VerifyErrorKind::ArgumentCount {
+                index: 0,
+                callee: "@callee".into(),
+                expected: 1,
+                found: 0,
+            },
+            VerifyErrorKind::TerminatorNotLast(1),
+            VerifyErrorKind::UnknownTarget(2),
+        ]
+    );
+}
+
+#[test]
+fn value_outside_the_layout_is_undefined() {
+    let mut program = Program::new();
+    let main = program.new_func(FunctionData::new(
+        "@main".into(),
+        Vec::new(),
+        Type::get_i32(),
+    ));
+    let func = program.func_mut(main);
+    let entry = func.dfg_mut().new_bb().basic_block(Some("%entry".into()));
+    let one = func.dfg_mut().new_value().integer(1);
+    let orphan = func.dfg_mut().new_value().binary(BinaryOp::Add, one, one);
+    let ret = func.dfg_mut().new_value().ret(Some(orphan));
+    func.layout_mut().bbs_mut().extend([entry]);
+    func.layout_mut().bb_mut(entry).insts_mut().extend([ret]);
+    assert_eq!(error_kinds(&program), [VerifyErrorKind::UndefinedValue(0)]);
+}